@@ -0,0 +1,135 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use arboard::Clipboard;
+use blaze_explorer_lib::action::{Action, AppAction};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    //On X11 and Wayland the copied text is served by the clipboard which set it, so it is kept
+    //for the lifetime of the plugin instead of being dropped after copying
+    static ref CLIPBOARD: Mutex<Option<Clipboard>> = Mutex::new(None);
+}
+
+/// Actions applied at once to every marked result (or the selected one if nothing is marked)
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchAction {
+    CopyPaths,
+    Delete,
+    Move,
+}
+
+/// Batch action waiting for the user to confirm it, together with the paths it was started on
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingBatch {
+    pub action: BatchAction,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Put the paths on the system clipboard, one per line. Returns a message for the user.
+pub fn copy_paths(paths: &[PathBuf]) -> String {
+    let text = paths
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<String>>()
+        .join("\n");
+    let mut clipboard = match CLIPBOARD.lock() {
+        Ok(clipboard) => clipboard,
+        Err(_) => return "Could not access the clipboard".to_string(),
+    };
+    if clipboard.is_none() {
+        match Clipboard::new() {
+            Ok(new_clipboard) => *clipboard = Some(new_clipboard),
+            Err(e) => return format!("Could not access the clipboard: {}", e),
+        }
+    }
+    match clipboard.as_mut().map(|clipboard| clipboard.set_text(text)) {
+        Some(Ok(())) => format!("Copied {} path(s)", paths.len()),
+        Some(Err(e)) => format!("Could not access the clipboard: {}", e),
+        None => "Could not access the clipboard".to_string(),
+    }
+}
+
+/// Delete the paths through the explorer, so that its confirmation and undo apply
+pub fn delete_paths(paths: &[PathBuf]) -> Action {
+    Action::AppAct(AppAction::DeletePaths(paths.to_vec()))
+}
+
+/// Move the paths into the destination directory through the explorer, so that its undo applies
+/// and names already in the destination are handled as when moving files in the explorer
+pub fn move_paths(paths: &[PathBuf], destination: &Path) -> Action {
+    match destination.is_dir() {
+        true => Action::AppAct(AppAction::MovePaths(
+            paths.to_vec(),
+            destination.to_path_buf(),
+        )),
+        false => Action::AppAct(AppAction::DisplayMessage(format!(
+            "{} is not a directory",
+            destination.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn create_files(dir: &TempDir, names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                fs::write(&path, name).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    fn message(action: Action) -> String {
+        match action {
+            Action::AppAct(AppAction::DisplayMessage(message)) => message,
+            other => panic!("expected a message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_delete_paths() {
+        let dir = TempDir::new().unwrap();
+        let paths = create_files(&dir, &["a.txt", "b.txt"]);
+
+        assert_eq!(
+            delete_paths(&paths),
+            Action::AppAct(AppAction::DeletePaths(paths.clone()))
+        );
+        assert!(paths.iter().all(|path| path.exists()));
+    }
+
+    #[test]
+    fn test_move_into_directory() {
+        let dir = TempDir::new().unwrap();
+        let paths = create_files(&dir, &["a.txt", "b.txt"]);
+        let destination = dir.path().join("destination");
+        fs::create_dir(&destination).unwrap();
+
+        assert_eq!(
+            move_paths(&paths, &destination),
+            Action::AppAct(AppAction::MovePaths(paths.clone(), destination))
+        );
+    }
+
+    #[test]
+    fn test_move_to_file_is_refused() {
+        let dir = TempDir::new().unwrap();
+        let paths = create_files(&dir, &["a.txt", "b.txt"]);
+
+        assert_eq!(
+            message(move_paths(&paths[..1], &paths[1])),
+            format!("{} is not a directory", paths[1].display())
+        );
+        assert!(paths[0].exists());
+    }
+}
@@ -0,0 +1,559 @@
+//Configuration of the plugin, read from telescope.json in the platform config directory (e.g.
+//~/.config/blaze_explorer/telescope.json on Linux). Every entry is optional and missing entries
+//keep their defaults, as do the entries which can't be used. An example:
+//{
+//    "search": { "max_results": 500, "hidden": false, "path_display": "basename_first" },
+//    "layout": { "strategy": "vertical", "prompt_position": "top", "preview_size": 50 },
+//    "preview": { "max_bytes": 65536, "syntax_theme": "InspiredGitHub" },
+//    "theme": { "match_highlight": "#ffaf00" },
+//    "pickers": {
+//        "files": { "columns": ["size", "modified"], "sort": "modified" },
+//        "live_grep": { "mode": "smart_case", "layout": "horizontal" }
+//    }
+//}
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use directories::ProjectDirs;
+use ratatui::style::Color;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::{
+    extended_query::QueryMode,
+    file_columns::{ResultColumn, SortMode},
+    layout::{LayoutConfig, LayoutStrategy},
+    path_display::PathDisplay,
+    syntax_highlight::{theme_names, DEFAULT_THEME},
+};
+
+const CONFIG_FILE_NAME: &str = "telescope.json";
+const DEFAULT_MAX_RESULTS: usize = 1000;
+const DEFAULT_MAX_COMMITS: usize = 10000;
+const DEFAULT_PREVIEW_BYTES: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelescopeConfig {
+    pub search: SearchConfig,
+    pub layout: LayoutConfig,
+    pub preview: PreviewConfig,
+    pub theme: ThemeConfig,
+    pub pickers: PickersConfig,
+}
+
+/// Options shared by the searches, which the pickers can override
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Maximum number of results kept by a search
+    pub max_results: usize,
+    /// Whether hidden files are searched
+    pub hidden: bool,
+    /// Whether files excluded by .gitignore, .ignore and the global git excludes are searched
+    pub ignored: bool,
+    /// Deepest level of directories walked below the search root, without a limit if missing
+    pub max_depth: Option<usize>,
+    /// How the query is matched: ignore_case, smart_case, exact_case or regex
+    pub mode: QueryMode,
+    /// Metadata shown next to the files found: type, size, modified and permissions
+    pub columns: Vec<ResultColumn>,
+    /// Order of the files found: score, basename, natural, modified, size, type or permissions
+    pub sort: SortMode,
+    /// How paths are shown: relative, absolute, basename_first, shorten or truncate
+    pub path_display: PathDisplay,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_results: DEFAULT_MAX_RESULTS,
+            hidden: true,
+            ignored: false,
+            max_depth: None,
+            mode: QueryMode::default(),
+            columns: Vec::new(),
+            sort: SortMode::default(),
+            path_display: PathDisplay::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    /// Maximum number of bytes read from a file to preview it
+    pub max_bytes: usize,
+    pub syntax_highlighting: bool,
+    /// Syntax highlighting theme bundled with syntect, e.g. InspiredGitHub for light terminals
+    pub syntax_theme: String,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_PREVIEW_BYTES,
+            syntax_highlighting: true,
+            syntax_theme: DEFAULT_THEME.to_string(),
+        }
+    }
+}
+
+/// Colours of the plugin, added to the theme of the explorer
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// Colour of the matched characters, as a name or a hex code like #ffaf00. They are yellow
+    /// when it is missing, or red on a yellow selected row
+    #[serde(deserialize_with = "deserialize_colour")]
+    pub match_highlight: Option<Color>,
+}
+
+fn deserialize_colour<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Color>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Color::from_str(&name)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("unknown colour {}", name)))
+}
+
+/// Options of a single picker, falling back to the shared ones when missing
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PickerConfig {
+    pub max_results: Option<usize>,
+    pub hidden: Option<bool>,
+    pub ignored: Option<bool>,
+    pub max_depth: Option<usize>,
+    pub mode: Option<QueryMode>,
+    pub columns: Option<Vec<ResultColumn>>,
+    pub sort: Option<SortMode>,
+    pub path_display: Option<PathDisplay>,
+    pub layout: Option<LayoutStrategy>,
+}
+
+impl PickerConfig {
+    /// The options set here, falling back to the ones of the other picker config
+    fn or(self, base: &PickerConfig) -> PickerConfig {
+        PickerConfig {
+            max_results: self.max_results.or(base.max_results),
+            hidden: self.hidden.or(base.hidden),
+            ignored: self.ignored.or(base.ignored),
+            max_depth: self.max_depth.or(base.max_depth),
+            mode: self.mode.or(base.mode),
+            columns: self.columns.or_else(|| base.columns.clone()),
+            sort: self.sort.or(base.sort),
+            path_display: self.path_display.or(base.path_display),
+            layout: self.layout.or(base.layout),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PickersConfig {
+    pub files: PickerConfig,
+    pub live_grep: PickerConfig,
+    pub git_log: PickerConfig,
+    pub git_status: PickerConfig,
+    pub keymaps: PickerConfig,
+}
+
+impl Default for PickersConfig {
+    fn default() -> Self {
+        Self {
+            files: PickerConfig::default(),
+            live_grep: PickerConfig::default(),
+            git_log: PickerConfig {
+                max_results: Some(DEFAULT_MAX_COMMITS),
+                ..PickerConfig::default()
+            },
+            git_status: PickerConfig::default(),
+            keymaps: PickerConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Picker {
+    Files,
+    LiveGrep,
+    GitLog,
+    GitStatus,
+    Keymaps,
+}
+
+impl TelescopeConfig {
+    fn picker(&self, picker: Picker) -> &PickerConfig {
+        match picker {
+            Picker::Files => &self.pickers.files,
+            Picker::LiveGrep => &self.pickers.live_grep,
+            Picker::GitLog => &self.pickers.git_log,
+            Picker::GitStatus => &self.pickers.git_status,
+            Picker::Keymaps => &self.pickers.keymaps,
+        }
+    }
+
+    /// Search options of the picker, with its overrides applied
+    pub fn search_config(&self, picker: Picker) -> SearchConfig {
+        let overrides = self.picker(picker);
+        SearchConfig {
+            max_results: overrides.max_results.unwrap_or(self.search.max_results),
+            hidden: overrides.hidden.unwrap_or(self.search.hidden),
+            ignored: overrides.ignored.unwrap_or(self.search.ignored),
+            max_depth: overrides.max_depth.or(self.search.max_depth),
+            mode: overrides.mode.unwrap_or(self.search.mode),
+            columns: overrides
+                .columns
+                .clone()
+                .unwrap_or_else(|| self.search.columns.clone()),
+            sort: overrides.sort.unwrap_or(self.search.sort),
+            path_display: overrides.path_display.unwrap_or(self.search.path_display),
+        }
+    }
+
+    pub fn layout_config(&self, picker: Picker) -> LayoutConfig {
+        let mut layout = self.layout.clone();
+        if let Some(strategy) = self.picker(picker).layout {
+            layout.strategy = strategy;
+        }
+        layout
+    }
+
+    /// Reset the values which can't be used to their defaults, describing each of them
+    fn validate(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let defaults = TelescopeConfig::default();
+        if self.search.max_results == 0 {
+            problems.push("search.max_results must be greater than 0".to_string());
+            self.search.max_results = defaults.search.max_results;
+        }
+        if self.search.max_depth == Some(0) {
+            problems.push("search.max_depth must be greater than 0".to_string());
+            self.search.max_depth = None;
+        }
+        if self.preview.max_bytes == 0 {
+            problems.push("preview.max_bytes must be greater than 0".to_string());
+            self.preview.max_bytes = defaults.preview.max_bytes;
+        }
+        let themes = theme_names();
+        if !themes.contains(&self.preview.syntax_theme.as_str()) {
+            let mut themes = themes;
+            themes.sort();
+            problems.push(format!(
+                "preview.syntax_theme must be one of {}",
+                themes.join(", ")
+            ));
+            self.preview.syntax_theme = defaults.preview.syntax_theme;
+        }
+        for (name, value, default) in [
+            ("width", &mut self.layout.width, defaults.layout.width),
+            ("height", &mut self.layout.height, defaults.layout.height),
+            (
+                "dropdown_width",
+                &mut self.layout.dropdown_width,
+                defaults.layout.dropdown_width,
+            ),
+        ] {
+            if !(1..=100).contains(value) {
+                problems.push(format!("layout.{} must be between 1 and 100", name));
+                *value = default;
+            }
+        }
+        if !(1..=99).contains(&self.layout.preview_size) {
+            problems.push("layout.preview_size must be between 1 and 99".to_string());
+            self.layout.preview_size = defaults.layout.preview_size;
+        }
+        for (name, picker) in [
+            ("files", &mut self.pickers.files),
+            ("live_grep", &mut self.pickers.live_grep),
+            ("git_log", &mut self.pickers.git_log),
+            ("git_status", &mut self.pickers.git_status),
+            ("keymaps", &mut self.pickers.keymaps),
+        ] {
+            if picker.max_results == Some(0) {
+                problems.push(format!(
+                    "pickers.{}.max_results must be greater than 0",
+                    name
+                ));
+                picker.max_results = None;
+            }
+            if picker.max_depth == Some(0) {
+                problems.push(format!("pickers.{}.max_depth must be greater than 0", name));
+                picker.max_depth = None;
+            }
+        }
+        problems
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "blaze_explorer").map(|dirs| dirs.config_dir().join(CONFIG_FILE_NAME))
+}
+
+/// Load the configuration, falling back to the defaults for anything which can't be used. The
+/// message describes what was wrong with the file, to be shown to the user.
+pub fn load_config() -> (TelescopeConfig, Option<String>) {
+    match config_path() {
+        Some(path) => load_config_from(&path),
+        None => (TelescopeConfig::default(), None),
+    }
+}
+
+pub fn load_config_from(path: &Path) -> (TelescopeConfig, Option<String>) {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return (TelescopeConfig::default(), None),
+        Err(e) => {
+            return (
+                TelescopeConfig::default(),
+                Some(format!("Could not read {}: {}", path.display(), e)),
+            )
+        }
+    };
+    let (config, problems) = parse_config(&contents);
+    let message = problems.map(|p| format!("Invalid Telescope config {}: {}", path.display(), p));
+    (config, message)
+}
+
+/// Parse the configuration entry by entry, so that a mistake only discards the entries it is in
+fn parse_config(contents: &str) -> (TelescopeConfig, Option<String>) {
+    let value = match serde_json::from_str::<Value>(contents) {
+        Ok(value) => value,
+        Err(e) => return (TelescopeConfig::default(), Some(e.to_string())),
+    };
+    let mut problems = Vec::new();
+    let mut config = TelescopeConfig::default();
+    for (key, value) in as_object("", value, &mut problems) {
+        match key.as_str() {
+            "search" => config.search = parse_section("search.", value, &mut problems),
+            "layout" => config.layout = parse_section("layout.", value, &mut problems),
+            "preview" => config.preview = parse_section("preview.", value, &mut problems),
+            "theme" => config.theme = parse_section("theme.", value, &mut problems),
+            "pickers" => {
+                for (name, value) in as_object("pickers.", value, &mut problems) {
+                    let prefix = format!("pickers.{}.", name);
+                    let picker = match name.as_str() {
+                        "files" => &mut config.pickers.files,
+                        "live_grep" => &mut config.pickers.live_grep,
+                        "git_log" => &mut config.pickers.git_log,
+                        "git_status" => &mut config.pickers.git_status,
+                        "keymaps" => &mut config.pickers.keymaps,
+                        _ => {
+                            problems.push(format!("pickers.{}: unknown key", name));
+                            continue;
+                        }
+                    };
+                    //keep the defaults of the picker, such as the commit limit of git_log
+                    *picker =
+                        parse_section::<PickerConfig>(&prefix, value, &mut problems).or(picker);
+                }
+            }
+            _ => problems.push(format!("{}: unknown key", key)),
+        }
+    }
+    problems.extend(config.validate());
+    match problems.is_empty() {
+        true => (config, None),
+        false => (config, Some(problems.join("; "))),
+    }
+}
+
+/// Entries of the object at the prefix, or none if it is not an object
+fn as_object(prefix: &str, value: Value, problems: &mut Vec<String>) -> Map<String, Value> {
+    match value {
+        Value::Object(entries) => entries,
+        _ => {
+            let name = match prefix.trim_end_matches('.') {
+                "" => "the configuration",
+                name => name,
+            };
+            problems.push(format!("{} must be an object", name));
+            Map::new()
+        }
+    }
+}
+
+/// Deserialize the section one key at a time, leaving out the keys which can't be used and
+/// describing each of them
+fn parse_section<T: DeserializeOwned + Default>(
+    prefix: &str,
+    value: Value,
+    problems: &mut Vec<String>,
+) -> T {
+    let mut accepted = Map::new();
+    for (key, value) in as_object(prefix, value, problems) {
+        let mut candidate = accepted.clone();
+        candidate.insert(key.clone(), value);
+        match serde_json::from_value::<T>(Value::Object(candidate.clone())) {
+            Ok(_) => accepted = candidate,
+            Err(e) if e.to_string().starts_with("unknown field") => {
+                problems.push(format!("{}{}: unknown key", prefix, key))
+            }
+            Err(e) => problems.push(format!("{}{}: {}", prefix, key, e)),
+        }
+    }
+    serde_json::from_value(Value::Object(accepted)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::PromptPosition;
+
+    #[test]
+    fn test_parse_partial_config() {
+        let (config, problems) = parse_config(
+            r##"{
+                "search": { "hidden": false },
+                "layout": { "strategy": "dropdown", "prompt_position": "top" },
+                "pickers": {
+                    "files": {
+                        "columns": ["size", "modified"],
+                        "sort": "natural",
+                        "path_display": "truncate"
+                    },
+                    "live_grep": { "max_results": 50, "layout": "vertical", "mode": "regex" }
+                },
+                "theme": { "match_highlight": "#ffaf00" }
+            }"##,
+        );
+        assert_eq!(problems, None);
+        assert_eq!(config.theme.match_highlight, Some(Color::Rgb(255, 175, 0)));
+        assert!(!config.search.hidden);
+        assert_eq!(config.search.max_results, DEFAULT_MAX_RESULTS);
+        assert_eq!(config.layout.prompt_position, PromptPosition::Top);
+
+        let grep = config.search_config(Picker::LiveGrep);
+        assert_eq!(grep.max_results, 50);
+        assert!(!grep.hidden);
+        assert_eq!(grep.mode, QueryMode::Regex);
+        assert_eq!(
+            config.search_config(Picker::Files).mode,
+            QueryMode::IgnoreCase
+        );
+        assert_eq!(
+            config.search_config(Picker::Files).columns,
+            vec![ResultColumn::Size, ResultColumn::Modified]
+        );
+        assert!(grep.columns.is_empty());
+        assert_eq!(config.search_config(Picker::Files).sort, SortMode::Natural);
+        assert_eq!(grep.sort, SortMode::Score);
+        assert_eq!(
+            config.search_config(Picker::Files).path_display,
+            PathDisplay::Truncate
+        );
+        assert_eq!(grep.path_display, PathDisplay::Relative);
+        assert_eq!(
+            config.layout_config(Picker::LiveGrep).strategy,
+            LayoutStrategy::Vertical
+        );
+        assert_eq!(
+            config.layout_config(Picker::Files).strategy,
+            LayoutStrategy::Dropdown
+        );
+        assert_eq!(
+            config.search_config(Picker::GitLog).max_results,
+            DEFAULT_MAX_COMMITS
+        );
+    }
+
+    #[test]
+    fn test_partial_picker_keeps_its_defaults() {
+        let (config, problems) =
+            parse_config(r#"{ "pickers": { "git_log": { "layout": "vertical" } } }"#);
+        assert_eq!(problems, None);
+        assert_eq!(
+            config.search_config(Picker::GitLog).max_results,
+            DEFAULT_MAX_COMMITS
+        );
+        assert_eq!(
+            config.layout_config(Picker::GitLog).strategy,
+            LayoutStrategy::Vertical
+        );
+    }
+
+    #[test]
+    fn test_invalid_entries_fall_back_to_defaults() {
+        let (config, problems) = parse_config(
+            r#"{
+                "search": { "max_result": 10, "hidden": false },
+                "layout": { "strategy": "diagonal", "prompt_position": "top" },
+                "pickers": { "file": {}, "live_grep": { "max_results": 50, "mode": 1 } },
+                "colours": {},
+                "theme": { "match_highlight": "ultraviolet" }
+            }"#,
+        );
+        assert!(!config.search.hidden);
+        assert_eq!(config.search.max_results, DEFAULT_MAX_RESULTS);
+        assert_eq!(config.layout.strategy, LayoutConfig::default().strategy);
+        assert_eq!(config.layout.prompt_position, PromptPosition::Top);
+        assert_eq!(config.search_config(Picker::LiveGrep).max_results, 50);
+        let problems = problems.unwrap();
+        let keys = problems
+            .split("; ")
+            .map(|problem| problem.split(':').next().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            keys,
+            vec![
+                "colours",
+                "layout.strategy",
+                "pickers.file",
+                "pickers.live_grep.mode",
+                "search.max_result",
+                "theme.match_highlight"
+            ]
+        );
+        assert_eq!(config.theme.match_highlight, None);
+        assert!(problems
+            .starts_with("colours: unknown key; layout.strategy: unknown variant `diagonal`"));
+
+        let (config, problems) = parse_config(r#"{ "search": { "hidden": false "#);
+        assert_eq!(config, TelescopeConfig::default());
+        assert!(problems.is_some());
+        let (config, problems) = parse_config(r#"{ "search": [] }"#);
+        assert_eq!(config, TelescopeConfig::default());
+        assert_eq!(problems, Some("search must be an object".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_values_are_reset() {
+        let (config, problems) = parse_config(
+            r#"{ "search": { "max_results": 0 }, "layout": { "width": 150, "height": 60 } }"#,
+        );
+        assert_eq!(config.search.max_results, DEFAULT_MAX_RESULTS);
+        assert_eq!(config.layout.width, LayoutConfig::default().width);
+        assert_eq!(config.layout.height, 60);
+        assert_eq!(
+            problems,
+            Some(
+                "search.max_results must be greater than 0; layout.width must be between 1 and 100"
+                    .to_string()
+            )
+        );
+
+        let (config, problems) = parse_config(r#"{ "preview": { "syntax_theme": "Monokai" } }"#);
+        assert_eq!(config.preview.syntax_theme, DEFAULT_THEME);
+        assert!(problems
+            .unwrap()
+            .starts_with("preview.syntax_theme must be one of "));
+        let (config, problems) =
+            parse_config(r#"{ "preview": { "syntax_theme": "InspiredGitHub" } }"#);
+        assert_eq!(config.preview.syntax_theme, "InspiredGitHub");
+        assert_eq!(problems, None);
+    }
+
+    #[test]
+    fn test_missing_config_file() {
+        let (config, problems) = load_config_from(Path::new("tests/no_such_config.json"));
+        assert_eq!(config, TelescopeConfig::default());
+        assert_eq!(problems, None);
+    }
+}
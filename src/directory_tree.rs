@@ -0,0 +1,319 @@
+//Preview of a directory as a depth-limited tree, in the style of the `tree` command
+
+use std::{
+    collections::HashMap,
+    fs::{self, FileType, Metadata},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use crate::file_preview::format_size;
+
+//Deepest level of the tree which is listed
+const TREE_MAX_DEPTH: usize = 3;
+//Number of entries counted towards the total size of a directory before giving up
+const MAX_SIZE_ENTRIES: usize = 100_000;
+//How long a computed total size is used before it is computed again
+const TOTAL_SIZE_TTL: Duration = Duration::from_secs(10);
+//Number of directories whose total size is kept
+const MAX_CACHED_SIZES: usize = 256;
+
+lazy_static! {
+    static ref TOTAL_SIZES: Mutex<HashMap<PathBuf, SizeState>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TotalSize {
+    bytes: u64,
+    //False if the walk stopped at MAX_SIZE_ENTRIES
+    complete: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SizeState {
+    //Being computed in the background, with the previous total if there was one
+    Computing(Option<TotalSize>),
+    Computed(TotalSize, Instant),
+}
+
+#[derive(Debug)]
+struct TreeEntry {
+    path: PathBuf,
+    name: String,
+    //Type of the entry itself, not following symlinks
+    file_type: Option<FileType>,
+}
+
+impl TreeEntry {
+    fn is_dir(&self) -> bool {
+        self.file_type.is_some_and(|t| t.is_dir())
+    }
+}
+
+/// A header with the number of children and the total size of the directory, followed by the
+/// tree listing. The total size is computed in the background, so it is missing from the first
+/// previews of large directories.
+pub fn directory_preview(path: &Path, max_lines: usize) -> Vec<Line<'static>> {
+    let children = match sorted_entries(path) {
+        Ok(children) => children,
+        Err(e) => return vec![Line::from(format!("Could not read the directory: {}", e))],
+    };
+    let header = match total_size(path) {
+        Some(size) if size.complete => {
+            format!(
+                "{} entries, {} in total",
+                children.len(),
+                format_size(size.bytes, false)
+            )
+        }
+        Some(size) => format!(
+            "{} entries, more than {} in total",
+            children.len(),
+            format_size(size.bytes, false)
+        ),
+        None => format!("{} entries, computing the total size", children.len()),
+    };
+    let mut lines = vec![Line::from(header), Line::default()];
+    push_tree_lines(&children, "", 1, max_lines, &mut lines);
+    lines
+}
+
+/// Whether the total size of the directory is still being computed, in which case its preview
+/// will change once it is done
+pub fn is_computing_size(path: &Path) -> bool {
+    let sizes = TOTAL_SIZES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    matches!(sizes.get(path), Some(SizeState::Computing(_)))
+}
+
+/// The last computed total size of the directory. It is computed again in the background when
+/// it is missing or out of date.
+fn total_size(path: &Path) -> Option<TotalSize> {
+    let mut sizes = TOTAL_SIZES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let previous = match sizes.get(path) {
+        Some(SizeState::Computing(previous)) => return *previous,
+        Some(SizeState::Computed(size, computed)) if computed.elapsed() < TOTAL_SIZE_TTL => {
+            return Some(*size)
+        }
+        Some(SizeState::Computed(size, _)) => Some(*size),
+        None => None,
+    };
+    if sizes.len() >= MAX_CACHED_SIZES {
+        sizes.retain(|_, state| matches!(state, SizeState::Computing(_)));
+    }
+    sizes.insert(path.to_path_buf(), SizeState::Computing(previous));
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        let size = walk_size(&path);
+        TOTAL_SIZES
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(path, SizeState::Computed(size, Instant::now()));
+    });
+    previous
+}
+
+/// Sum of the sizes of the files in the directory and its subdirectories, without following
+/// symlinks
+fn walk_size(path: &Path) -> TotalSize {
+    let mut size = TotalSize {
+        bytes: 0,
+        complete: true,
+    };
+    let mut counted = 0;
+    let mut directories = vec![path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            counted += 1;
+            if counted > MAX_SIZE_ENTRIES {
+                size.complete = false;
+                return size;
+            }
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => directories.push(entry.path()),
+                Ok(file_type) if file_type.is_file() => {
+                    size.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0)
+                }
+                _ => {}
+            }
+        }
+    }
+    size
+}
+
+/// Entries of the directory, subdirectories first
+fn sorted_entries(path: &Path) -> io::Result<Vec<TreeEntry>> {
+    let mut entries = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| TreeEntry {
+            path: entry.path(),
+            name: entry.file_name().to_string_lossy().to_string(),
+            file_type: entry.file_type().ok(),
+        })
+        .collect::<Vec<TreeEntry>>();
+    entries.sort_by(|a, b| b.is_dir().cmp(&a.is_dir()).then(a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+fn push_tree_lines(
+    entries: &[TreeEntry],
+    prefix: &str,
+    depth: usize,
+    max_lines: usize,
+    lines: &mut Vec<Line<'static>>,
+) {
+    for (id, entry) in entries.iter().enumerate() {
+        if lines.len() >= max_lines {
+            return;
+        }
+        let is_last = id + 1 == entries.len();
+        let branch = match is_last {
+            true => "└── ",
+            false => "├── ",
+        };
+        lines.push(tree_line(&format!("{}{}", prefix, branch), entry));
+        //The subdirectory is only read if some of its entries fit
+        if entry.is_dir() && depth < TREE_MAX_DEPTH && lines.len() < max_lines {
+            if let Ok(children) = sorted_entries(&entry.path) {
+                let child_prefix = match is_last {
+                    true => format!("{}    ", prefix),
+                    false => format!("{}│   ", prefix),
+                };
+                push_tree_lines(&children, &child_prefix, depth + 1, max_lines, lines);
+            }
+        }
+    }
+}
+
+fn tree_line(prefix: &str, entry: &TreeEntry) -> Line<'static> {
+    let secondary = Style::default().fg(Color::DarkGray);
+    let name_style = match entry.is_dir() {
+        true => Style::default()
+            .fg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+        false => Style::default(),
+    };
+    //Only the listed entries are stat'ed
+    let metadata = fs::symlink_metadata(&entry.path).ok();
+    let mut spans = vec![
+        Span::styled(prefix.to_string(), secondary),
+        Span::styled(entry.name.clone(), name_style),
+        Span::raw(type_marker(metadata.as_ref())),
+    ];
+    if let Some(metadata) = metadata.as_ref().filter(|m| m.is_file()) {
+        spans.push(Span::styled(
+            format!("  {}", format_size(metadata.len(), false)),
+            secondary,
+        ));
+    }
+    Line::from(spans)
+}
+
+/// Suffix describing the type of the entry, as used by `ls -F`
+fn type_marker(metadata: Option<&Metadata>) -> &'static str {
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => return "?",
+    };
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        return "/";
+    }
+    if file_type.is_symlink() {
+        return "@";
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+        if file_type.is_fifo() {
+            return "|";
+        }
+        if file_type.is_socket() {
+            return "=";
+        }
+        if file_type.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+            return "*";
+        }
+    }
+    ""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_size(path: &str) -> String {
+        format_size(fs::metadata(path).unwrap().len(), false)
+    }
+
+    #[test]
+    fn test_directory_preview() {
+        let path = Path::new("tests/folder_1");
+        let mut lines = directory_preview(path, 10);
+        let started = Instant::now();
+        while is_computing_size(path) && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if lines[0].to_string().ends_with("computing the total size") {
+            lines = directory_preview(path, 10);
+        }
+        let total = fs::metadata("tests/folder_1/example_file.xlsx")
+            .unwrap()
+            .len()
+            + fs::metadata("tests/folder_1/example_text.txt")
+                .unwrap()
+                .len();
+        assert_eq!(
+            lines
+                .iter()
+                .map(|line| line.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                format!("2 entries, {} in total", format_size(total, false)),
+                String::new(),
+                format!(
+                    "├── example_file.xlsx  {}",
+                    fixture_size("tests/folder_1/example_file.xlsx")
+                ),
+                format!(
+                    "└── example_text.txt  {}",
+                    fixture_size("tests/folder_1/example_text.txt")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_total_size_counts_nested_files() {
+        let nested = walk_size(Path::new("tests/folder_1"));
+        let size = walk_size(Path::new("tests"));
+        assert!(size.complete);
+        assert!(size.bytes > nested.bytes);
+    }
+
+    #[test]
+    fn test_directory_preview_fits_area() {
+        let lines = directory_preview(Path::new("tests"), 4);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].to_string().ends_with("── folder_1/"));
+        assert!(lines[3]
+            .to_string()
+            .ends_with("   ├── example_file.xlsx  6.1 KB"));
+    }
+}
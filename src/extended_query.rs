@@ -0,0 +1,425 @@
+//Extended query syntax, following the extended search mode of fzf. The query is split on spaces
+//into terms which all have to match, while terms joined by `|` only need one of them to match:
+//  foo     fuzzy match (a substring match for the sources which don't match fuzzily)
+//  'foo    exact match, the text contains foo
+//  ^foo    the text starts with foo
+//  foo$    the text ends with foo
+//  ^foo$   the text is foo
+//  !foo    inverse exact match, the text doesn't contain foo (also !^foo, !foo$ and !^foo$)
+//A space is part of a term when escaped with a backslash, e.g. `foo\ bar`.
+//The query can be matched as a regular expression instead, depending on the query mode.
+
+use std::fmt::{self, Display};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::fuzzy::{
+    fuzzy_match_with_case, score_positions, substring_positions_with_case, FuzzyMatch,
+};
+
+/// How the terms without an operator are matched by a source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    Fuzzy,
+    Substring,
+}
+
+/// How the text of the query is matched, cycled through from the popup
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMode {
+    #[default]
+    IgnoreCase,
+    /// Case sensitive only when the query contains uppercase characters
+    SmartCase,
+    ExactCase,
+    /// The query is a regular expression rather than extended query terms
+    Regex,
+}
+
+impl QueryMode {
+    pub fn next(self) -> Self {
+        match self {
+            QueryMode::IgnoreCase => QueryMode::SmartCase,
+            QueryMode::SmartCase => QueryMode::ExactCase,
+            QueryMode::ExactCase => QueryMode::Regex,
+            QueryMode::Regex => QueryMode::IgnoreCase,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            QueryMode::IgnoreCase => "ignore case",
+            QueryMode::SmartCase => "smart case",
+            QueryMode::ExactCase => "exact case",
+            QueryMode::Regex => "regex",
+        }
+    }
+}
+
+/// Query parsed according to the query mode
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextQuery {
+    Extended {
+        query: ExtendedQuery,
+        ignore_case: bool,
+    },
+    Regex(QueryRegex),
+}
+
+/// Compiled regular expression, compared by its pattern
+#[derive(Debug, Clone)]
+pub struct QueryRegex(Regex);
+
+impl PartialEq for QueryRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Default for TextQuery {
+    fn default() -> Self {
+        TextQuery::Extended {
+            query: ExtendedQuery::default(),
+            ignore_case: true,
+        }
+    }
+}
+
+impl TextQuery {
+    pub fn parse(query: &str, mode: QueryMode) -> Result<Self, QueryError> {
+        let ignore_case = match mode {
+            QueryMode::IgnoreCase => true,
+            QueryMode::SmartCase => !query.chars().any(char::is_uppercase),
+            QueryMode::ExactCase => false,
+            QueryMode::Regex => {
+                return match query.is_empty() {
+                    true => Ok(TextQuery::default()),
+                    false => Regex::new(query)
+                        .map(|regex| TextQuery::Regex(QueryRegex(regex)))
+                        .map_err(|e| QueryError::new(regex_error(e))),
+                }
+            }
+        };
+        Ok(TextQuery::Extended {
+            query: ExtendedQuery::parse(query)?,
+            ignore_case,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            TextQuery::Extended { query, .. } => query.is_empty(),
+            TextQuery::Regex(_) => false,
+        }
+    }
+
+    /// Match the text against the query. Regular expressions are scored like an exact match of
+    /// their first match.
+    pub fn matches(&self, text: &str, mode: MatchMode) -> Option<FuzzyMatch> {
+        match self {
+            TextQuery::Extended { query, ignore_case } => query.matches(text, mode, *ignore_case),
+            TextQuery::Regex(QueryRegex(regex)) => {
+                let found = regex.find(text)?;
+                let start = text[..found.start()].chars().count();
+                let positions = (start..start + found.as_str().chars().count()).collect::<Vec<_>>();
+                Some(FuzzyMatch {
+                    score: score_positions(text, &positions),
+                    positions,
+                })
+            }
+        }
+    }
+}
+
+/// Last line of the regex error, which describes the problem without repeating the pattern
+fn regex_error(error: regex::Error) -> String {
+    let message = error.to_string();
+    let reason = message
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or(&message)
+        .trim()
+        .trim_start_matches("error: ")
+        .to_string();
+    format!("invalid regex: {}", reason)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TermKind {
+    Default,
+    Exact,
+    Prefix,
+    Suffix,
+    Equal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    kind: TermKind,
+    inverse: bool,
+    text: String,
+}
+
+/// Parsed query - a text has to match one of the terms of every group
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExtendedQuery {
+    groups: Vec<Vec<Term>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError(String);
+
+impl QueryError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ExtendedQuery {
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let mut groups: Vec<Vec<Term>> = Vec::new();
+        let mut pending_or = false;
+        for token in split_terms(query) {
+            if token == "|" {
+                if groups.is_empty() || pending_or {
+                    return Err(QueryError("`|` needs a term on both sides".to_string()));
+                }
+                pending_or = true;
+                continue;
+            }
+            let term = parse_term(&token)?;
+            match (pending_or, groups.last_mut()) {
+                (true, Some(group)) => group.push(term),
+                _ => groups.push(vec![term]),
+            }
+            pending_or = false;
+        }
+        if pending_or {
+            return Err(QueryError("`|` needs a term on both sides".to_string()));
+        }
+        Ok(Self { groups })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Match the text against every group, adding up the scores and the matched characters of
+    /// the best term of each group. An empty query matches everything with a score of 0.
+    pub fn matches(&self, text: &str, mode: MatchMode, ignore_case: bool) -> Option<FuzzyMatch> {
+        let mut result = FuzzyMatch::default();
+        for group in &self.groups {
+            let best = group
+                .iter()
+                .filter_map(|term| term.matches(text, mode, ignore_case))
+                .max_by_key(|m| m.score)?;
+            result.score += best.score;
+            result.positions.extend(best.positions);
+        }
+        result.positions.sort_unstable();
+        result.positions.dedup();
+        Some(result)
+    }
+}
+
+impl Term {
+    fn matches(&self, text: &str, mode: MatchMode, ignore_case: bool) -> Option<FuzzyMatch> {
+        let positions = match (self.kind, mode) {
+            (TermKind::Default, MatchMode::Fuzzy) if !self.inverse => {
+                return fuzzy_match_with_case(&self.text, text, ignore_case)
+            }
+            (TermKind::Default | TermKind::Exact, _) => {
+                substring_positions_with_case(&self.text, text, ignore_case)
+            }
+            (TermKind::Prefix, _) => anchored_positions(&self.text, text, ignore_case, true),
+            (TermKind::Suffix, _) => anchored_positions(&self.text, text, ignore_case, false),
+            (TermKind::Equal, _) => match text.chars().count() == self.text.chars().count() {
+                true => anchored_positions(&self.text, text, ignore_case, true),
+                false => None,
+            },
+        };
+        match (positions, self.inverse) {
+            (Some(_), true) => None,
+            (None, true) => Some(FuzzyMatch::default()),
+            (Some(positions), false) => Some(FuzzyMatch {
+                score: score_positions(text, &positions),
+                positions,
+            }),
+            (None, false) => None,
+        }
+    }
+}
+
+/// Whether everything matching `query` also matches `previous`, so that the results of the
+/// previous query only need to be narrowed down
+pub fn narrows(previous: &str, query: &str) -> bool {
+    query.starts_with(previous) && !query.contains(['|', '!']) && !previous.contains(['$', '\\'])
+}
+
+/// Split the query on spaces which are not escaped with a backslash
+pub fn split_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut chars = query.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => current.push(chars.next().unwrap_or(' ')),
+            ' ' => {
+                if !current.is_empty() {
+                    terms.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms
+}
+
+fn parse_term(token: &str) -> Result<Term, QueryError> {
+    let (inverse, rest) = match token.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    let (kind, text) = if let Some(text) = rest.strip_prefix('\'') {
+        (TermKind::Exact, text)
+    } else if let Some(text) = rest.strip_prefix('^') {
+        match text.strip_suffix('$') {
+            Some(text) => (TermKind::Equal, text),
+            None => (TermKind::Prefix, text),
+        }
+    } else if let Some(text) = rest.strip_suffix('$') {
+        (TermKind::Suffix, text)
+    } else {
+        (TermKind::Default, rest)
+    };
+    if text.is_empty() {
+        return Err(QueryError(format!("`{}` has nothing to match", token)));
+    }
+    Ok(Term {
+        kind,
+        inverse,
+        text: text.to_string(),
+    })
+}
+
+/// Positions of the term at the start (or the end) of the text
+fn anchored_positions(
+    term: &str,
+    text: &str,
+    ignore_case: bool,
+    at_start: bool,
+) -> Option<Vec<usize>> {
+    let text_length = text.chars().count();
+    let term_length = term.chars().count();
+    if term_length > text_length {
+        return None;
+    }
+    let start = match at_start {
+        true => 0,
+        false => text_length - term_length,
+    };
+    let anchored = text
+        .chars()
+        .skip(start)
+        .take(term_length)
+        .collect::<String>();
+    substring_positions_with_case(term, &anchored, ignore_case)
+        .map(|positions| positions.into_iter().map(|p| p + start).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(query: &str, text: &str) -> bool {
+        ExtendedQuery::parse(query)
+            .unwrap()
+            .matches(text, MatchMode::Fuzzy, true)
+            .is_some()
+    }
+
+    #[test]
+    fn test_term_operators() {
+        assert!(is_match("tbk", "src/telescope_backend.rs"));
+        assert!(!is_match("'tbk", "src/telescope_backend.rs"));
+        assert!(is_match("^src .rs$", "src/telescope_backend.rs"));
+        assert!(!is_match("^tele", "src/telescope_backend.rs"));
+        assert!(is_match("!test", "src/telescope_backend.rs"));
+        assert!(!is_match("!^src", "src/telescope_backend.rs"));
+        assert!(is_match("^lib.rs$", "lib.rs"));
+        assert!(!is_match("^lib.rs$", "src/lib.rs"));
+        assert!(is_match("file\\ name", "my file name.txt"));
+    }
+
+    #[test]
+    fn test_or_groups() {
+        let query = ExtendedQuery::parse("^src .toml$ | .rs$").unwrap();
+        let result = query
+            .matches("src/lib.rs", MatchMode::Substring, true)
+            .unwrap();
+        assert_eq!(result.positions, vec![0, 1, 2, 7, 8, 9]);
+        assert!(query
+            .matches("tests/lib.rs", MatchMode::Substring, true)
+            .is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(ExtendedQuery::parse("| foo").is_err());
+        assert!(ExtendedQuery::parse("foo |").is_err());
+        assert!(ExtendedQuery::parse("foo | | bar").is_err());
+        assert_eq!(
+            ExtendedQuery::parse("foo !"),
+            Err(QueryError("`!` has nothing to match".to_string()))
+        );
+        assert!(ExtendedQuery::parse("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_query_modes() {
+        let is_match = |query: &str, mode: QueryMode, text: &str| {
+            TextQuery::parse(query, mode)
+                .unwrap()
+                .matches(text, MatchMode::Fuzzy)
+                .is_some()
+        };
+        assert!(is_match("readme", QueryMode::SmartCase, "README.md"));
+        assert!(!is_match("ReadMe", QueryMode::SmartCase, "README.md"));
+        assert!(!is_match("readme", QueryMode::ExactCase, "README.md"));
+        assert!(is_match(
+            "^src/.*_tele",
+            QueryMode::Regex,
+            "src/sfs_telescope.rs"
+        ));
+        assert!(!is_match("^tele", QueryMode::Regex, "src/sfs_telescope.rs"));
+
+        let found = TextQuery::parse("[0-9]+", QueryMode::Regex)
+            .unwrap()
+            .matches("file_42.rs", MatchMode::Fuzzy)
+            .unwrap();
+        assert_eq!(found.positions, vec![5, 6]);
+        let error = TextQuery::parse("(unclosed", QueryMode::Regex).unwrap_err();
+        assert_eq!(error.to_string(), "invalid regex: unclosed group");
+    }
+
+    #[test]
+    fn test_narrows() {
+        assert!(narrows("fo", "foo"));
+        assert!(narrows("foo", "foo ^src"));
+        assert!(!narrows("foo", "foo | bar"));
+        assert!(!narrows("foo$", "foo$x"));
+        assert!(!narrows("!fo", "!foo"));
+    }
+}
@@ -0,0 +1,287 @@
+//Metadata columns shown next to the paths in the file search, and the orders the results can be
+//sorted in
+
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+
+use crate::{file_preview::format_size, file_query::FileInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultColumn {
+    Type,
+    Size,
+    Modified,
+    Permissions,
+}
+
+impl ResultColumn {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResultColumn::Type => "Type",
+            ResultColumn::Size => "Size",
+            ResultColumn::Modified => "Modified",
+            ResultColumn::Permissions => "Permissions",
+        }
+    }
+
+    pub fn format(&self, info: &FileInfo) -> String {
+        match self {
+            ResultColumn::Type => file_type(info).to_string(),
+            ResultColumn::Size if info.is_dir => "-".to_string(),
+            ResultColumn::Size => format_size(info.size, true),
+            ResultColumn::Modified => info
+                .modified
+                .map(|time| {
+                    DateTime::<Local>::from(time)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_else(|| "-".to_string()),
+            ResultColumn::Permissions => format_permissions(info),
+        }
+    }
+
+    /// Order of two files by the column. Sizes and modification times go from the largest and
+    /// newest, the rest alphabetically.
+    pub fn compare(&self, a: &FileInfo, b: &FileInfo) -> Ordering {
+        match self {
+            ResultColumn::Type => file_type(a).cmp(file_type(b)),
+            ResultColumn::Size => b.size.cmp(&a.size),
+            ResultColumn::Modified => b.modified.cmp(&a.modified),
+            ResultColumn::Permissions => format_permissions(a).cmp(&format_permissions(b)),
+        }
+    }
+}
+
+/// Orders the results of the file search can be sorted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortMode {
+    /// Best match first
+    #[default]
+    Score,
+    /// Alphabetically by file name
+    Basename,
+    /// By path, with the numbers in it compared by value (file2 before file10)
+    Natural,
+    /// Most recently modified first
+    Modified,
+    /// Largest first
+    Size,
+    Type,
+    Permissions,
+}
+
+const CYCLED_SORT_MODES: [SortMode; 5] = [
+    SortMode::Score,
+    SortMode::Basename,
+    SortMode::Natural,
+    SortMode::Modified,
+    SortMode::Size,
+];
+
+impl SortMode {
+    /// The next mode, going through the score, names, modification time and size, and then the
+    /// other shown columns
+    pub fn next(&self, columns: &[ResultColumn]) -> Self {
+        let mut modes = CYCLED_SORT_MODES.to_vec();
+        for column in columns {
+            let mode = SortMode::from(*column);
+            if !modes.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+        let position = modes.iter().position(|mode| mode == self).unwrap_or(0);
+        modes[(position + 1) % modes.len()]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortMode::Score => "score",
+            SortMode::Basename => "basename",
+            SortMode::Natural => "natural",
+            SortMode::Modified => "modified",
+            SortMode::Size => "size",
+            SortMode::Type => "type",
+            SortMode::Permissions => "permissions",
+        }
+    }
+
+    /// Order of two files by the mode, or `Equal` when sorting by score. Files without metadata
+    /// go last when sorting by it.
+    pub fn compare(
+        &self,
+        (a_path, a_info): (&str, Option<&FileInfo>),
+        (b_path, b_info): (&str, Option<&FileInfo>),
+    ) -> Ordering {
+        let column = match self {
+            SortMode::Score => return Ordering::Equal,
+            SortMode::Basename => {
+                return basename(a_path)
+                    .to_lowercase()
+                    .cmp(&basename(b_path).to_lowercase())
+            }
+            SortMode::Natural => return natural_cmp(a_path, b_path),
+            SortMode::Modified => ResultColumn::Modified,
+            SortMode::Size => ResultColumn::Size,
+            SortMode::Type => ResultColumn::Type,
+            SortMode::Permissions => ResultColumn::Permissions,
+        };
+        match (a_info, b_info) {
+            (Some(a_info), Some(b_info)) => column.compare(a_info, b_info),
+            _ => b_info.is_some().cmp(&a_info.is_some()),
+        }
+    }
+}
+
+impl From<ResultColumn> for SortMode {
+    fn from(column: ResultColumn) -> Self {
+        match column {
+            ResultColumn::Type => SortMode::Type,
+            ResultColumn::Size => SortMode::Size,
+            ResultColumn::Modified => SortMode::Modified,
+            ResultColumn::Permissions => SortMode::Permissions,
+        }
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Compare the texts alphabetically, ignoring case, except for runs of digits which are compared
+/// by their value
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a_chunks, b_chunks) = (digit_chunks(a), digit_chunks(b));
+    for (a_chunk, b_chunk) in a_chunks.iter().zip(b_chunks.iter()) {
+        let is_number = |chunk: &str| chunk.starts_with(|ch: char| ch.is_ascii_digit());
+        let ordering = match (is_number(a_chunk), is_number(b_chunk)) {
+            (true, true) => {
+                let a_digits = a_chunk.trim_start_matches('0');
+                let b_digits = b_chunk.trim_start_matches('0');
+                a_digits
+                    .len()
+                    .cmp(&b_digits.len())
+                    .then(a_digits.cmp(b_digits))
+            }
+            _ => a_chunk.to_lowercase().cmp(&b_chunk.to_lowercase()),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then(a.cmp(b))
+}
+
+/// Split the text into alternating runs of digits and of other characters
+fn digit_chunks(text: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut previous_digit = None;
+    for (i, ch) in text.char_indices() {
+        let digit = ch.is_ascii_digit();
+        if previous_digit.is_some_and(|previous| previous != digit) {
+            chunks.push(&text[start..i]);
+            start = i;
+        }
+        previous_digit = Some(digit);
+    }
+    if start < text.len() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+}
+
+fn file_type(info: &FileInfo) -> &'static str {
+    match (info.is_symlink, info.is_dir) {
+        (true, _) => "link",
+        (false, true) => "dir",
+        (false, false) => "file",
+    }
+}
+
+/// Permissions in the style of `ls -l`, e.g. rwxr-xr-x
+fn format_permissions(info: &FileInfo) -> String {
+    let flags = ['r', 'w', 'x'];
+    (0..9)
+        .map(|bit| match info.mode & (1 << (8 - bit)) {
+            0 => '-',
+            _ => flags[bit % 3],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(is_dir: bool, size: u64, mode: u32) -> FileInfo {
+        FileInfo {
+            is_dir,
+            is_symlink: false,
+            size,
+            modified: None,
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_format_columns() {
+        let file = info(false, 1536, 0o754);
+        assert_eq!(ResultColumn::Type.format(&file), "file");
+        assert_eq!(ResultColumn::Size.format(&file), "1.5K");
+        assert_eq!(ResultColumn::Permissions.format(&file), "rwxr-xr--");
+        assert_eq!(ResultColumn::Modified.format(&file), "-");
+        assert_eq!(ResultColumn::Size.format(&info(true, 4096, 0o755)), "-");
+    }
+
+    #[test]
+    fn test_sort_modes() {
+        let columns = [ResultColumn::Size, ResultColumn::Permissions];
+        let mut mode = SortMode::default();
+        let mut labels = Vec::new();
+        for _ in 0..7 {
+            mode = mode.next(&columns);
+            labels.push(mode.label());
+        }
+        assert_eq!(
+            labels,
+            vec![
+                "basename",
+                "natural",
+                "modified",
+                "size",
+                "permissions",
+                "score",
+                "basename"
+            ]
+        );
+
+        let (small, large) = (info(false, 1, 0o644), info(false, 2, 0o644));
+        assert_eq!(
+            SortMode::Size.compare(("a", Some(&large)), ("b", Some(&small))),
+            Ordering::Less
+        );
+        assert_eq!(
+            SortMode::Size.compare(("a", None), ("b", Some(&small))),
+            Ordering::Greater
+        );
+        assert_eq!(
+            SortMode::Basename.compare(("a/Zeta", None), ("z/alpha", None)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["file10.txt", "file2.txt", "File1.txt", "file02b", "file"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["file", "File1.txt", "file2.txt", "file02b", "file10.txt"]
+        );
+    }
+}
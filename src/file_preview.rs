@@ -0,0 +1,577 @@
+//Previews of file contents shared by the searches. Text files are syntax highlighted, directories
+//are shown as a tree and anything else as a hex dump together with the detected file type. Only
+//a bounded prefix of the file is read, and special files (pipes, devices etc.) are never opened,
+//so that previewing can't block the explorer. Previews are cached until the file changes, or for
+//a short while for directories, as they are requested on every frame.
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File, Metadata},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use lazy_static::lazy_static;
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use crate::{
+    config::PreviewConfig,
+    directory_tree::{directory_preview, is_computing_size},
+    syntax_highlight::highlight_contents,
+};
+
+//Number of bytes shown on a single line of the hex dump
+const HEX_DUMP_WIDTH: usize = 16;
+//Number of bytes inspected when looking for NUL bytes
+const BINARY_SNIFF_LENGTH: usize = 8192;
+//Bytes read per line of the preview area, bounded below and above
+const PREVIEW_BYTES_PER_LINE: usize = 256;
+const MIN_PREVIEW_BYTES: usize = 4096;
+//Previews are loaded for at least this many lines, and then for twice as many as before, so that
+//scrolling doesn't read the file again on every step
+const MIN_PREVIEW_LINES: usize = 64;
+//Upper bound on the memory taken by cached previews
+const MAX_CACHE_BYTES: usize = 8 << 20;
+//How long directory previews are kept. Changes deeper in the tree don't change the modification
+//time of the directory itself, so its previews can't be kept until it changes.
+const DIRECTORY_PREVIEW_TTL: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    static ref PREVIEW_CACHE: Mutex<PreviewCache> = Mutex::new(PreviewCache::new(MAX_CACHE_BYTES));
+    static ref PREVIEW_CONFIG: Mutex<PreviewConfig> = Mutex::new(PreviewConfig::default());
+}
+
+/// Use the preview options from the configuration. Cached previews made with other options are
+/// dropped.
+pub fn configure(config: &PreviewConfig) {
+    let mut current = PREVIEW_CONFIG
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if *current != *config {
+        *current = config.clone();
+        PREVIEW_CACHE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+fn preview_config() -> PreviewConfig {
+    PREVIEW_CONFIG
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// The first `max_lines` lines of the text, highlighted unless disabled in the configuration
+pub fn text_lines(path: &Path, contents: &str, max_lines: usize) -> Vec<Line<'static>> {
+    let config = preview_config();
+    match config.syntax_highlighting {
+        true => highlight_contents(path, contents, max_lines, &config.syntax_theme),
+        false => contents
+            .lines()
+            .take(max_lines)
+            .map(|line| Line::from(line.to_string()))
+            .collect(),
+    }
+}
+
+//Signatures at the very start of common binary formats
+const MAGIC_NUMBERS: [(&[u8], &str); 16] = [
+    (b"PK\x03\x04", "Zip archive"),
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xff\xd8\xff", "JPEG image"),
+    (b"GIF87a", "GIF image"),
+    (b"GIF89a", "GIF image"),
+    (b"%PDF-", "PDF document"),
+    (b"\x7fELF", "ELF executable"),
+    (b"\x1f\x8b", "Gzip archive"),
+    (b"BZh", "Bzip2 archive"),
+    (b"\xfd7zXZ\x00", "XZ archive"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (b"Rar!\x1a\x07", "RAR archive"),
+    (b"SQLite format 3\x00", "SQLite database"),
+    (b"\x00asm", "WebAssembly module"),
+    (b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+    (
+        b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+        "Legacy Office document",
+    ),
+];
+
+/// Contents of a file, classified for previewing
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileContents {
+    Text(String),
+    Binary {
+        file_type: Option<&'static str>,
+        bytes: Vec<u8>,
+    },
+}
+
+impl FileContents {
+    /// Treat the bytes as binary if they start with a known signature, contain NUL bytes or are
+    /// not valid UTF-8. A character cut in half at the end of the bytes is dropped, as the bytes
+    /// may be just the beginning of the file.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        let file_type = detect_file_type(&bytes);
+        let has_nul = bytes.iter().take(BINARY_SNIFF_LENGTH).any(|b| *b == 0);
+        if file_type.is_some() || has_nul {
+            return FileContents::Binary { file_type, bytes };
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => FileContents::Text(text),
+            Err(e) if e.utf8_error().error_len().is_none() => {
+                let valid_length = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid_length);
+                FileContents::Text(String::from_utf8(bytes).unwrap_or_default())
+            }
+            Err(e) => FileContents::Binary {
+                file_type,
+                bytes: e.into_bytes(),
+            },
+        }
+    }
+}
+
+/// Lines previewing the file at the path, enough to fill `max_lines` rows. There can be more
+/// lines than that, as the previews are loaded ahead for scrolling.
+pub fn preview_lines(path: &Path, max_lines: usize) -> Vec<Line<'static>> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return vec![Line::from(format!("Could not read the file: {}", e))],
+    };
+    if let Some(kind) = special_file_kind(&metadata) {
+        return vec![Line::from(format!("Not previewing {}", kind))];
+    }
+    //Files reporting no size (like the ones in /proc) can change without their metadata changing,
+    //so they are always read again
+    if metadata.len() == 0 {
+        return read_preview(path, &metadata, max_lines);
+    }
+    let key = PreviewKey {
+        path: path.to_path_buf(),
+        modified: metadata.modified().ok(),
+        size: metadata.len(),
+    };
+    let mut cache = PREVIEW_CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(lines) = cache.get(&key, max_lines) {
+        return lines;
+    }
+    let loaded_lines = max_lines.max(MIN_PREVIEW_LINES).next_power_of_two();
+    let lines = read_preview(path, &metadata, loaded_lines);
+    //Previews waiting for the total size of the directory are made again on the next draw
+    let expires = match metadata.is_dir() {
+        true if is_computing_size(path) => Some(Instant::now()),
+        true => Some(Instant::now() + DIRECTORY_PREVIEW_TTL),
+        false => None,
+    };
+    cache.insert(key, loaded_lines, lines.clone(), expires);
+    lines
+}
+
+fn read_preview(path: &Path, metadata: &Metadata, max_lines: usize) -> Vec<Line<'static>> {
+    if metadata.is_dir() {
+        return directory_preview(path, max_lines);
+    }
+    let limit = preview_byte_limit(max_lines);
+    let (bytes, truncated) = match read_prefix(path, limit) {
+        Ok(prefix) => prefix,
+        Err(e) => return vec![Line::from(format!("Could not read the file: {}", e))],
+    };
+    //Keep the last row for the note about the part of the file which was not loaded
+    let max_content_lines = match truncated {
+        true => max_lines.saturating_sub(1),
+        false => max_lines,
+    };
+    let mut lines = match FileContents::from_bytes(bytes) {
+        FileContents::Text(text) => text_lines(path, &text, max_content_lines),
+        FileContents::Binary { file_type, bytes } => {
+            binary_preview(file_type, &bytes, metadata.len(), max_content_lines)
+        }
+    };
+    if truncated {
+        lines.push(not_loaded_line(limit, metadata.len()));
+    }
+    lines
+}
+
+/// Identifies a preview of a particular version of the file
+#[derive(Debug, Clone, PartialEq)]
+struct PreviewKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    key: PreviewKey,
+    //Number of rows the preview was loaded for, independent of the rows currently shown
+    loaded_lines: usize,
+    lines: Vec<Line<'static>>,
+    size: usize,
+    //When the preview goes stale, for previews which can't be kept until the file changes
+    expires: Option<Instant>,
+}
+
+/// Previews ordered from the least to the most recently used, bounded by their approximate size
+#[derive(Debug, Default)]
+struct PreviewCache {
+    entries: VecDeque<CacheEntry>,
+    used_bytes: usize,
+    max_bytes: usize,
+}
+
+impl PreviewCache {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// The preview of the file, if it was loaded for at least `max_lines` rows
+    fn get(&mut self, key: &PreviewKey, max_lines: usize) -> Option<Vec<Line<'static>>> {
+        let id = self
+            .entries
+            .iter()
+            .position(|entry| entry.key == *key && entry.loaded_lines >= max_lines)?;
+        let entry = self.entries.remove(id)?;
+        if entry
+            .expires
+            .is_some_and(|expires| expires <= Instant::now())
+        {
+            self.used_bytes -= entry.size;
+            return None;
+        }
+        let lines = entry.lines.clone();
+        self.entries.push_back(entry);
+        Some(lines)
+    }
+
+    /// Store the preview, replacing the previews of older versions of the same file and evicting
+    /// the least recently used ones until the cache fits in memory again
+    fn insert(
+        &mut self,
+        key: PreviewKey,
+        loaded_lines: usize,
+        lines: Vec<Line<'static>>,
+        expires: Option<Instant>,
+    ) {
+        let stale = self
+            .entries
+            .iter()
+            .filter(|entry| entry.key.path == key.path)
+            .map(|entry| entry.size)
+            .sum::<usize>();
+        self.entries.retain(|entry| entry.key.path != key.path);
+        self.used_bytes -= stale;
+
+        let size = estimate_size(&lines);
+        if size > self.max_bytes {
+            return;
+        }
+        self.entries.push_back(CacheEntry {
+            key,
+            loaded_lines,
+            lines,
+            size,
+            expires,
+        });
+        self.used_bytes += size;
+        while self.used_bytes > self.max_bytes {
+            match self.entries.pop_front() {
+                Some(entry) => self.used_bytes -= entry.size,
+                None => break,
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.used_bytes = 0;
+    }
+}
+
+fn estimate_size(lines: &[Line]) -> usize {
+    lines
+        .iter()
+        .map(|line| {
+            std::mem::size_of::<Line>()
+                + line
+                    .spans
+                    .iter()
+                    .map(|span| std::mem::size_of::<Span>() + span.content.len())
+                    .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Description of the file if it is not a regular file or directory which can be safely read
+pub fn special_file_kind(metadata: &Metadata) -> Option<&'static str> {
+    let file_type = metadata.file_type();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return Some("a named pipe");
+        }
+        if file_type.is_socket() {
+            return Some("a socket");
+        }
+        if file_type.is_char_device() || file_type.is_block_device() {
+            return Some("a device");
+        }
+    }
+    match file_type.is_file() || file_type.is_dir() {
+        true => None,
+        false => Some("a special file"),
+    }
+}
+
+/// Number of bytes to read to fill the preview area
+fn preview_byte_limit(max_lines: usize) -> usize {
+    (max_lines * PREVIEW_BYTES_PER_LINE)
+        .max(MIN_PREVIEW_BYTES)
+        .min(preview_config().max_bytes)
+}
+
+/// Read at most `limit` bytes from the start of the file, and tell whether there was more.
+/// Files such as the ones in /proc report a size of 0, so the size in the metadata is not trusted.
+fn read_prefix(path: &Path, limit: usize) -> io::Result<(Vec<u8>, bool)> {
+    let mut bytes = Vec::new();
+    File::open(path)?
+        .take(limit as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    let truncated = bytes.len() > limit;
+    bytes.truncate(limit);
+    Ok((bytes, truncated))
+}
+
+fn not_loaded_line(loaded: usize, file_size: u64) -> Line<'static> {
+    let note = match file_size > loaded as u64 {
+        true => format!(
+            "… {} of {} not loaded",
+            format_size(file_size - loaded as u64, false),
+            format_size(file_size, false)
+        ),
+        false => format!(
+            "… only the first {} loaded",
+            format_size(loaded as u64, false)
+        ),
+    };
+    Line::styled(note, Style::default().fg(Color::DarkGray))
+}
+
+/// Human readable size, e.g. "1.5 MB", or "1.5M" in the compact form used by the columns
+pub fn format_size(bytes: u64, compact: bool) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    let (separator, suffix) = match (compact, unit) {
+        (true, _) => ("", UNITS[unit].to_string()),
+        (false, 0) => (" ", UNITS[unit].to_string()),
+        (false, _) => (" ", format!("{}B", UNITS[unit])),
+    };
+    match unit {
+        0 => format!("{}{}{}", bytes, separator, suffix),
+        _ => format!("{:.1}{}{}", size, separator, suffix),
+    }
+}
+
+fn detect_file_type(bytes: &[u8]) -> Option<&'static str> {
+    MAGIC_NUMBERS
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map(|(_, file_type)| *file_type)
+}
+
+/// A header with the file type and size, followed by the hex dump
+fn binary_preview(
+    file_type: Option<&'static str>,
+    bytes: &[u8],
+    file_size: u64,
+    max_lines: usize,
+) -> Vec<Line<'static>> {
+    let header = format!(
+        "Binary file - {} ({})",
+        file_type.unwrap_or("unknown type"),
+        format_size(file_size, false)
+    );
+    let mut lines = vec![Line::from(header), Line::default()];
+    lines.extend(
+        bytes
+            .chunks(HEX_DUMP_WIDTH)
+            .take(max_lines.saturating_sub(lines.len()))
+            .enumerate()
+            .map(|(id, chunk)| hex_dump_line(id * HEX_DUMP_WIDTH, chunk)),
+    );
+    lines
+}
+
+/// Format a chunk of bytes as "offset  hex bytes  |ascii|"
+fn hex_dump_line(offset: usize, chunk: &[u8]) -> Line<'static> {
+    let hex = (0..HEX_DUMP_WIDTH)
+        .map(|id| match chunk.get(id) {
+            Some(byte) => format!("{:02x}", byte),
+            None => "  ".to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
+    let ascii = chunk
+        .iter()
+        .map(|byte| match byte.is_ascii_graphic() || *byte == b' ' {
+            true => *byte as char,
+            false => '.',
+        })
+        .collect::<String>();
+    Line::from(vec![
+        Span::styled(
+            format!("{:08x}  ", offset),
+            Style::default().fg(Color::DarkGray),
+        ),
+        Span::raw(format!("{}  |{}|", hex, ascii)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_binary_contents() {
+        let xlsx = fs::read("tests/folder_1/example_file.xlsx").unwrap();
+        assert!(matches!(
+            FileContents::from_bytes(xlsx),
+            FileContents::Binary {
+                file_type: Some("Zip archive"),
+                ..
+            }
+        ));
+        assert!(matches!(
+            FileContents::from_bytes(vec![b'a', 0, b'b']),
+            FileContents::Binary {
+                file_type: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            FileContents::from_bytes(vec![0xff, 0xfe, b'a']),
+            FileContents::Binary { .. }
+        ));
+        assert_eq!(
+            FileContents::from_bytes("zebra ü".as_bytes().to_vec()),
+            FileContents::Text("zebra ü".to_string())
+        );
+        //The prefix of a file can end in the middle of a character
+        let cut = "zebra ü".as_bytes()[..7].to_vec();
+        assert_eq!(
+            FileContents::from_bytes(cut),
+            FileContents::Text("zebra ".to_string())
+        );
+    }
+
+    #[test]
+    fn test_read_bounded_prefix() {
+        let path = Path::new("tests/folder_1/example_text.txt");
+        let (bytes, truncated) = read_prefix(path, 7).unwrap();
+        assert_eq!(bytes, b"Example".to_vec());
+        assert!(truncated);
+        let (_, truncated) = read_prefix(path, MIN_PREVIEW_BYTES).unwrap();
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_special_files_are_not_previewed() {
+        let directory = fs::metadata("tests/folder_1").unwrap();
+        assert_eq!(special_file_kind(&directory), None);
+        let file = fs::metadata("tests/folder_1/example_text.txt").unwrap();
+        assert_eq!(special_file_kind(&file), None);
+        #[cfg(unix)]
+        {
+            let device = fs::metadata("/dev/null").unwrap();
+            assert_eq!(special_file_kind(&device), Some("a device"));
+        }
+    }
+
+    #[test]
+    fn test_preview_cache() {
+        let key = |path: &str, size: u64| PreviewKey {
+            path: PathBuf::from(path),
+            modified: None,
+            size,
+        };
+        let lines = vec![Line::from("x".repeat(100))];
+        let entry_size = estimate_size(&lines);
+        let mut cache = PreviewCache::new(entry_size * 2);
+        cache.insert(key("a", 1), 10, lines.clone(), None);
+        cache.insert(key("b", 1), 10, lines.clone(), None);
+        //Using "a" makes "b" the least recently used preview, which is evicted first
+        assert_eq!(cache.get(&key("a", 1), 10), Some(lines.clone()));
+        cache.insert(key("c", 1), 10, lines.clone(), None);
+        assert_eq!(cache.get(&key("b", 1), 10), None);
+        assert!(cache.get(&key("a", 1), 10).is_some());
+
+        //Previews loaded for more rows serve the smaller areas, but not the larger ones
+        assert!(cache.get(&key("a", 1), 5).is_some());
+        assert_eq!(cache.get(&key("a", 1), 11), None);
+
+        //A new version of the file replaces the stale preview
+        cache.insert(key("a", 2), 10, lines.clone(), None);
+        assert_eq!(cache.get(&key("a", 1), 10), None);
+        assert!(cache.get(&key("a", 2), 10).is_some());
+        assert_eq!(cache.used_bytes, entry_size * 2);
+
+        //Expired previews are dropped
+        cache.insert(key("a", 3), 10, lines.clone(), Some(Instant::now()));
+        assert_eq!(cache.get(&key("a", 3), 10), None);
+        assert_eq!(cache.used_bytes, entry_size);
+    }
+
+    #[test]
+    fn test_scrolling_reuses_the_loaded_preview() {
+        let path = Path::new("src/file_preview.rs");
+        let lines = preview_lines(path, 10);
+        assert_eq!(lines.len(), MIN_PREVIEW_LINES);
+        assert_eq!(preview_lines(path, 11), lines);
+        assert_eq!(
+            preview_lines(path, MIN_PREVIEW_LINES + 1).len(),
+            2 * MIN_PREVIEW_LINES
+        );
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512, false), "512 B");
+        assert_eq!(format_size(1536, false), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024, false), "3.0 MB");
+        assert_eq!(format_size(12, true), "12B");
+        assert_eq!(format_size(1536, true), "1.5K");
+        assert_eq!(format_size(3 << 30, true), "3.0G");
+    }
+
+    #[test]
+    fn test_hex_dump_line() {
+        let line = hex_dump_line(32, b"PK\x03\x04 hi");
+        assert_eq!(
+            line.to_string(),
+            format!(
+                "00000020  50 4b 03 04 20 68 69{}  |PK.. hi|",
+                "   ".repeat(HEX_DUMP_WIDTH - 7)
+            )
+        );
+    }
+}
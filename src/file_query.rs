@@ -0,0 +1,443 @@
+//Query of the file search - the extended query syntax, mixed with predicates on the metadata of
+//the files:
+//  ext:rs / ext:rs,toml      the extension is one of the listed ones
+//  type:file / type:dir / type:symlink (or f, d, l)
+//  size:>10M / size:<=4k / size:0     compared with the size, in bytes or with a K, M, G or T unit
+//  mtime:<7d / mtime:>2w     modified less (more) than the given time ago, in s, m, h, d, w or y
+//  mtime:>2024-01-31         modified after the given day, `mtime:2024-01-31` during that day
+//A predicate can be negated with `!`, e.g. `!ext:lock`.
+
+use std::{
+    fs::Metadata,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::extended_query::{narrows, split_terms, QueryError, QueryMode, TextQuery};
+
+const SIZE_UNITS: [(&str, u64); 5] = [
+    ("b", 1),
+    ("k", 1 << 10),
+    ("m", 1 << 20),
+    ("g", 1 << 30),
+    ("t", 1 << 40),
+];
+const TIME_UNITS: [(&str, u64); 6] = [
+    ("s", 1),
+    ("m", 60),
+    ("h", 60 * 60),
+    ("d", 24 * 60 * 60),
+    ("w", 7 * 24 * 60 * 60),
+    ("y", 365 * 24 * 60 * 60),
+];
+
+/// Metadata of a file, gathered when walking the search root
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileInfo {
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// Permission bits of the owner, the group and the others, as in `ls -l`
+    pub mode: u32,
+}
+
+impl From<&Metadata> for FileInfo {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            is_dir: metadata.is_dir(),
+            is_symlink: metadata.is_symlink(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            mode: permission_bits(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+/// Only the read only flag is known outside of unix, the rest is filled in like a usual file
+#[cfg(not(unix))]
+fn permission_bits(metadata: &Metadata) -> u32 {
+    match metadata.permissions().readonly() {
+        true => 0o444,
+        false => 0o644,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn holds<T: PartialOrd>(&self, value: T, bound: T) -> bool {
+        match self {
+            Comparison::Less => value < bound,
+            Comparison::LessOrEqual => value <= bound,
+            Comparison::Equal => value == bound,
+            Comparison::GreaterOrEqual => value >= bound,
+            Comparison::Greater => value > bound,
+        }
+    }
+
+    /// Split the comparison operator off the value, if there is one
+    fn split(value: &str) -> (Option<Self>, &str) {
+        for (operator, comparison) in [
+            (">=", Comparison::GreaterOrEqual),
+            ("<=", Comparison::LessOrEqual),
+            (">", Comparison::Greater),
+            ("<", Comparison::Less),
+            ("=", Comparison::Equal),
+        ] {
+            if let Some(rest) = value.strip_prefix(operator) {
+                return (Some(comparison), rest);
+            }
+        }
+        (None, value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Extension(Vec<String>),
+    Kind(FileKind),
+    Size(Comparison, u64),
+    //Modification time compared with a point in time
+    Modified(Comparison, SystemTime),
+    //Modification time within the given day
+    ModifiedOn(SystemTime, SystemTime),
+}
+
+impl Predicate {
+    fn parse(key: &str, value: &str, now: DateTime<Local>) -> Result<Self, String> {
+        if value.is_empty() {
+            return Err(format!("`{}:` needs a value", key));
+        }
+        match key {
+            "ext" => Ok(Predicate::Extension(
+                value
+                    .split(',')
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect(),
+            )),
+            "type" => match value {
+                "f" | "file" => Ok(Predicate::Kind(FileKind::File)),
+                "d" | "dir" | "directory" => Ok(Predicate::Kind(FileKind::Directory)),
+                "l" | "symlink" | "link" => Ok(Predicate::Kind(FileKind::Symlink)),
+                _ => Err(format!(
+                    "unknown type `{}`, use file, dir or symlink",
+                    value
+                )),
+            },
+            "size" => {
+                let (comparison, size) = Comparison::split(value);
+                let size = parse_size(size).ok_or(format!("invalid size `{}`", size))?;
+                Ok(Predicate::Size(
+                    comparison.unwrap_or(Comparison::Equal),
+                    size,
+                ))
+            }
+            "mtime" => parse_mtime(value, now),
+            _ => Err(format!("unknown predicate `{}:`", key)),
+        }
+    }
+
+    fn accepts(&self, path: &Path, info: &FileInfo) -> bool {
+        match self {
+            Predicate::Extension(extensions) => path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| extensions.contains(&ext)),
+            Predicate::Kind(kind) => match kind {
+                FileKind::File => !info.is_dir && !info.is_symlink,
+                FileKind::Directory => info.is_dir,
+                FileKind::Symlink => info.is_symlink,
+            },
+            Predicate::Size(comparison, size) => comparison.holds(info.size, *size),
+            Predicate::Modified(comparison, time) => info
+                .modified
+                .is_some_and(|modified| comparison.holds(modified, *time)),
+            Predicate::ModifiedOn(start, end) => info
+                .modified
+                .is_some_and(|modified| *start <= modified && modified < *end),
+        }
+    }
+}
+
+/// Parsed file search query - the predicates the files have to satisfy, and the free text part
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileQuery {
+    //Predicates with whether they are negated
+    predicates: Vec<(Predicate, bool)>,
+    //Tokens of the predicates as typed in
+    predicate_terms: Vec<String>,
+    text_query: String,
+    mode: QueryMode,
+    pub text: TextQuery,
+}
+
+impl FileQuery {
+    pub fn parse(query: &str, mode: QueryMode) -> Result<Self, QueryError> {
+        Self::parse_at(query, mode, Local::now())
+    }
+
+    /// Parse the query, with the times relative to `now`
+    pub fn parse_at(
+        query: &str,
+        mode: QueryMode,
+        now: DateTime<Local>,
+    ) -> Result<Self, QueryError> {
+        let terms = split_terms(query);
+        let mut file_query = FileQuery::default();
+        let mut text_terms = Vec::new();
+        for (id, term) in terms.iter().enumerate() {
+            let (negated, predicate) = match term.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, term.as_str()),
+            };
+            let (key, value) = match predicate.split_once(':') {
+                Some((key, value)) if is_predicate_key(key) => (key, value),
+                _ => {
+                    text_terms.push(term.replace(' ', "\\ "));
+                    continue;
+                }
+            };
+            let next_to_or =
+                |id: Option<usize>| id.and_then(|id| terms.get(id)).is_some_and(|t| t == "|");
+            if next_to_or(id.checked_sub(1)) || next_to_or(Some(id + 1)) {
+                return Err(QueryError::new(format!(
+                    "`{}` can't be combined with `|`",
+                    term
+                )));
+            }
+            let predicate = Predicate::parse(key, value, now).map_err(QueryError::new)?;
+            file_query.predicates.push((predicate, negated));
+            file_query.predicate_terms.push(term.clone());
+        }
+        file_query.text_query = text_terms.join(" ");
+        file_query.mode = mode;
+        file_query.text = TextQuery::parse(&file_query.text_query, mode)?;
+        Ok(file_query)
+    }
+
+    /// Whether the file satisfies every predicate. Files without metadata only pass queries
+    /// without predicates.
+    pub fn accepts(&self, path: &Path, info: Option<&FileInfo>) -> bool {
+        if self.predicates.is_empty() {
+            return true;
+        }
+        match info {
+            Some(info) => self
+                .predicates
+                .iter()
+                .all(|(predicate, negated)| predicate.accepts(path, info) != *negated),
+            None => false,
+        }
+    }
+
+    /// Whether everything matching this query also matches the previous one
+    pub fn narrows(&self, previous: &FileQuery) -> bool {
+        let text_narrows = match self.mode {
+            //A longer regular expression can match more
+            QueryMode::Regex => self.text_query == previous.text_query,
+            _ => narrows(&previous.text_query, &self.text_query),
+        };
+        self.mode == previous.mode
+            && self.predicate_terms == previous.predicate_terms
+            && text_narrows
+    }
+}
+
+fn is_predicate_key(key: &str) -> bool {
+    matches!(key, "ext" | "type" | "size" | "mtime")
+}
+
+/// Parse a size such as 512, 4k, 10M or 1.5GB into bytes
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.to_lowercase();
+    let number_end = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(number_end);
+    let number = number.parse::<f64>().ok()?;
+    let unit = unit.trim_end_matches("ib").trim_end_matches('b');
+    let multiplier = match unit {
+        "" => 1,
+        unit => SIZE_UNITS.iter().find(|(u, _)| *u == unit)?.1,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parse an age such as 30m, 7d or 2w, or return None if the value isn't an age
+fn parse_age(age: &str) -> Option<Result<Duration, String>> {
+    let number_end = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(number_end);
+    if number.is_empty() {
+        return None;
+    }
+    let seconds = TIME_UNITS.iter().find(|(u, _)| *u == unit)?.1;
+    let age = number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or(format!("time out of range `{}`", age));
+    Some(age)
+}
+
+fn parse_mtime(value: &str, now: DateTime<Local>) -> Result<Predicate, String> {
+    let (comparison, bound) = Comparison::split(value);
+    if let Some(age) = parse_age(bound) {
+        //A smaller age is a later modification time, so the comparison is flipped
+        let time = SystemTime::from(now)
+            .checked_sub(age?)
+            .ok_or(format!("time out of range `{}`", bound))?;
+        let comparison = match comparison.unwrap_or(Comparison::Less) {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Equal => Comparison::Equal,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            Comparison::Greater => Comparison::Less,
+        };
+        return Ok(Predicate::Modified(comparison, time));
+    }
+    let day = NaiveDate::parse_from_str(bound, "%Y-%m-%d").map_err(|_| {
+        format!(
+            "invalid time `{}`, use an age like 7d or a date like 2024-01-31",
+            bound
+        )
+    })?;
+    let start = start_of_day(day).ok_or(format!("invalid date `{}`", bound))?;
+    let end = day
+        .succ_opt()
+        .and_then(start_of_day)
+        .ok_or(format!("invalid date `{}`", bound))?;
+    Ok(match comparison {
+        None | Some(Comparison::Equal) => Predicate::ModifiedOn(start, end),
+        Some(Comparison::Less) => Predicate::Modified(Comparison::Less, start),
+        Some(Comparison::LessOrEqual) => Predicate::Modified(Comparison::Less, end),
+        Some(Comparison::GreaterOrEqual) => Predicate::Modified(Comparison::GreaterOrEqual, start),
+        Some(Comparison::Greater) => Predicate::Modified(Comparison::GreaterOrEqual, end),
+    })
+}
+
+fn start_of_day(day: NaiveDate) -> Option<SystemTime> {
+    day.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(SystemTime::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(size: u64, age: u64) -> FileInfo {
+        FileInfo {
+            is_dir: false,
+            is_symlink: false,
+            size,
+            modified: Some(SystemTime::now() - Duration::from_secs(age)),
+            mode: 0o644,
+        }
+    }
+
+    #[test]
+    fn test_predicates() {
+        let query = FileQuery::parse(
+            "report ext:pdf,PS size:>1M mtime:<7d",
+            QueryMode::IgnoreCase,
+        )
+        .unwrap();
+        let path = Path::new("docs/report.pdf");
+        let day = 24 * 60 * 60;
+        assert!(query.accepts(path, Some(&info(2 << 20, day))));
+        assert!(!query.accepts(path, Some(&info(1 << 10, day))));
+        assert!(!query.accepts(path, Some(&info(2 << 20, 8 * day))));
+        assert!(!query.accepts(Path::new("docs/report.txt"), Some(&info(2 << 20, day))));
+        assert!(!query.accepts(path, None));
+        assert_eq!(
+            query.text,
+            TextQuery::parse("report", QueryMode::IgnoreCase).unwrap()
+        );
+
+        let query = FileQuery::parse("!ext:lock type:f", QueryMode::IgnoreCase).unwrap();
+        assert!(query.accepts(Path::new("Cargo.toml"), Some(&info(0, 0))));
+        assert!(!query.accepts(Path::new("Cargo.lock"), Some(&info(0, 0))));
+    }
+
+    #[test]
+    fn test_mtime_dates() {
+        let now = Local::now();
+        let day = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let noon = SystemTime::from(
+            day.and_hms_opt(12, 0, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap(),
+        );
+        let file = FileInfo {
+            modified: Some(noon),
+            ..info(0, 0)
+        };
+        let accepts = |query: &str| {
+            FileQuery::parse_at(query, QueryMode::IgnoreCase, now)
+                .unwrap()
+                .accepts(Path::new("a"), Some(&file))
+        };
+        assert!(accepts("mtime:2024-01-31"));
+        assert!(accepts("mtime:>=2024-01-31"));
+        assert!(accepts("mtime:<2024-02-01"));
+        assert!(!accepts("mtime:>2024-01-31"));
+        assert!(!accepts("mtime:<7d"));
+    }
+
+    #[test]
+    fn test_invalid_predicates() {
+        assert_eq!(parse_size("1.5k"), Some(1536));
+        assert_eq!(parse_size("10MB"), Some(10 << 20));
+        assert!(FileQuery::parse("size:>lots", QueryMode::IgnoreCase).is_err());
+        assert!(FileQuery::parse("type:pipe", QueryMode::IgnoreCase).is_err());
+        assert!(FileQuery::parse("ext:", QueryMode::IgnoreCase).is_err());
+        assert!(FileQuery::parse("ext:rs | foo", QueryMode::IgnoreCase).is_err());
+        //Ages reaching before the epoch or past the largest number are errors, not panics
+        for query in [
+            "mtime:<500000000000y",
+            "mtime:<99999999999999999y",
+            "mtime:>99999999999999999999999d",
+        ] {
+            let error = FileQuery::parse(query, QueryMode::IgnoreCase).unwrap_err();
+            assert!(error.to_string().contains("time out of range"));
+        }
+        //Other words with a colon are searched for
+        assert!(FileQuery::parse("C:", QueryMode::IgnoreCase)
+            .unwrap()
+            .predicates
+            .is_empty());
+    }
+
+    #[test]
+    fn test_narrows() {
+        let parse = |query: &str| FileQuery::parse(query, QueryMode::IgnoreCase).unwrap();
+        assert!(parse("ext:rs tele").narrows(&parse("ext:rs te")));
+        assert!(!parse("size:<10").narrows(&parse("size:<1")));
+    }
+}
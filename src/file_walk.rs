@@ -0,0 +1,76 @@
+//Walk of the files below the search root, shared by the file search and live grep so that both
+//honour the same hidden, ignored and depth options
+
+use ignore::WalkBuilder;
+
+use crate::{config::SearchConfig, file_query::FileInfo};
+
+/// A file found by the walk, with the metadata used by the predicates of the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandidateFile {
+    pub path: String,
+    pub info: Option<FileInfo>,
+}
+
+/// Files and directories under the search root, with their metadata. Unless ignored files are
+/// searched, the rules from .gitignore, .ignore and the global git excludes are applied, also
+/// outside of a git repository, and the .git directory is skipped as well.
+pub fn walk_files(root: &str, options: &SearchConfig) -> impl Iterator<Item = CandidateFile> {
+    let respect_ignored = !options.ignored;
+    WalkBuilder::new(root)
+        .hidden(!options.hidden)
+        .ignore(respect_ignored)
+        .git_ignore(respect_ignored)
+        .git_global(respect_ignored)
+        .git_exclude(respect_ignored)
+        .parents(respect_ignored)
+        //.gitignore files also apply outside of git repositories
+        .require_git(false)
+        .max_depth(options.max_depth)
+        .filter_entry(move |entry| !(respect_ignored && entry.file_name() == ".git"))
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.depth() > 0)
+        .map(|entry| CandidateFile {
+            path: entry.path().display().to_string(),
+            info: entry
+                .metadata()
+                .ok()
+                .map(|metadata| FileInfo::from(&metadata)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_display::relative_path;
+
+    fn walked_names(options: &SearchConfig) -> Vec<String> {
+        let mut names = walk_files("tests/ignore_rules", options)
+            .map(|file| relative_path(&file.path, "tests/ignore_rules").to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_walk_respects_ignore_files() {
+        let mut options = SearchConfig {
+            hidden: false,
+            ..SearchConfig::default()
+        };
+        assert_eq!(walked_names(&options), vec!["kept.txt"]);
+
+        options.hidden = true;
+        assert_eq!(
+            walked_names(&options),
+            vec![".hidden.txt", ".ignore", "kept.txt"]
+        );
+
+        options.ignored = true;
+        assert_eq!(
+            walked_names(&options),
+            vec![".hidden.txt", ".ignore", "kept.txt", "skipped.log"]
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//Fuzzy matching in the spirit of fzf/telescope sorters. Every character of the query has to be
+//found in the text in order, and the alignment with the highest score is selected. Matches
+//score higher when they are contiguous, start at word boundaries and fall within the basename.
+
+const SCORE_MATCH: i64 = 16;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+const BONUS_CONSECUTIVE: i64 = 8;
+const BONUS_SEPARATOR: i64 = 10;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 7;
+const BONUS_BASENAME: i64 = 6;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Character (not byte) indices of the text matched by the query
+    pub positions: Vec<usize>,
+}
+
+/// Score the text against the query, ignoring case. Returns None if the query is not a
+/// subsequence of the text. An empty query matches everything with a score of 0.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    fuzzy_match_with_case(query, text, true)
+}
+
+/// Like `fuzzy_match`, comparing the case of the characters unless `ignore_case` is set
+pub fn fuzzy_match_with_case(query: &str, text: &str, ignore_case: bool) -> Option<FuzzyMatch> {
+    let fold = |c: char| match ignore_case {
+        true => fold_case(c),
+        false => c,
+    };
+    let query = query.chars().map(fold).collect::<Vec<char>>();
+    let original_text = text.chars().collect::<Vec<char>>();
+    let text = original_text
+        .iter()
+        .map(|c| fold(*c))
+        .collect::<Vec<char>>();
+    if query.is_empty() {
+        return Some(FuzzyMatch::default());
+    }
+    if !is_subsequence(&query, &text) {
+        return None;
+    }
+
+    let bonuses = position_bonuses(&original_text);
+
+    //scores[i][j] holds the best score of an alignment of query[..=i] ending at text[j], together
+    //with the position of query[i - 1] in that alignment
+    let mut scores: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; text.len()]; query.len()];
+    for (j, ch) in text.iter().enumerate() {
+        if *ch == query[0] {
+            scores[0][j] = Some((SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER, 0));
+        }
+    }
+    for i in 1..query.len() {
+        //Best predecessor separated from j by at least one unmatched character
+        let mut gapped_best: Option<(i64, usize)> = None;
+        for j in 1..text.len() {
+            if j >= 2 {
+                if let Some((score, _)) = scores[i - 1][j - 2] {
+                    let candidate = score - PENALTY_GAP_START;
+                    gapped_best = match gapped_best {
+                        Some((best, _)) if best - PENALTY_GAP_EXTENSION >= candidate => {
+                            gapped_best.map(|(best, k)| (best - PENALTY_GAP_EXTENSION, k))
+                        }
+                        _ => Some((candidate, j - 2)),
+                    };
+                } else {
+                    gapped_best = gapped_best.map(|(best, k)| (best - PENALTY_GAP_EXTENSION, k));
+                }
+            }
+            if text[j] != query[i] {
+                continue;
+            }
+            let consecutive =
+                scores[i - 1][j - 1].map(|(score, _)| (score + BONUS_CONSECUTIVE, j - 1));
+            let predecessor = match (consecutive, gapped_best) {
+                (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                (c, g) => c.or(g),
+            };
+            if let Some((score, k)) = predecessor {
+                scores[i][j] = Some((score + SCORE_MATCH + bonuses[j], k));
+            }
+        }
+    }
+
+    //Pick the best end position and trace the alignment back
+    let last = query.len() - 1;
+    let (mut j, score) = scores[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, s)| s.map(|(score, _)| (j, score)))
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))?;
+    let mut positions = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        positions[i] = j;
+        if let Some((_, k)) = scores[i][j] {
+            j = k;
+        }
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Score of the alignment of a query with the characters of the text at the given positions, the
+/// same way `fuzzy_match` scores its best alignment
+pub fn score_positions(text: &str, positions: &[usize]) -> i64 {
+    let bonuses = position_bonuses(&text.chars().collect::<Vec<char>>());
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    for &position in positions {
+        let bonus = bonuses.get(position).copied().unwrap_or(0);
+        score += match previous {
+            None => SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER,
+            Some(p) if p + 1 == position => SCORE_MATCH + bonus + BONUS_CONSECUTIVE,
+            Some(p) => {
+                let gap = position.saturating_sub(p + 1) as i64;
+                SCORE_MATCH + bonus - PENALTY_GAP_START - (gap - 1) * PENALTY_GAP_EXTENSION
+            }
+        };
+        previous = Some(position);
+    }
+    score
+}
+
+/// Find the first occurrence of the query in the text, ignoring case, and return the character
+/// positions it covers
+pub fn substring_positions(query: &str, text: &str) -> Option<Vec<usize>> {
+    substring_positions_with_case(query, text, true)
+}
+
+/// Like `substring_positions`, comparing the case of the characters unless `ignore_case` is set
+pub fn substring_positions_with_case(
+    query: &str,
+    text: &str,
+    ignore_case: bool,
+) -> Option<Vec<usize>> {
+    let fold = |c: char| match ignore_case {
+        true => fold_case(c),
+        false => c,
+    };
+    let query = query.chars().map(fold).collect::<Vec<char>>();
+    let text = text.chars().map(fold).collect::<Vec<char>>();
+    if query.is_empty() {
+        return Some(Vec::new());
+    }
+    let start = text
+        .windows(query.len())
+        .position(|window| window == query.as_slice())?;
+    Some((start..start + query.len()).collect())
+}
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == '\\'
+}
+
+fn is_subsequence(query: &[char], text: &[char]) -> bool {
+    let mut text_iter = text.iter();
+    query.iter().all(|q| text_iter.any(|t| t == q))
+}
+
+/// Bonuses for matching each character of the text, favouring the basename
+fn position_bonuses(text: &[char]) -> Vec<i64> {
+    let basename_start = text
+        .iter()
+        .rposition(|c| is_separator(*c))
+        .map(|id| id + 1)
+        .unwrap_or(0);
+    (0..text.len())
+        .map(|id| {
+            let bonus = position_bonus(text, id);
+            match id >= basename_start {
+                true => bonus + BONUS_BASENAME,
+                false => bonus,
+            }
+        })
+        .collect()
+}
+
+/// Bonus awarded for matching the character at the given position, based on its predecessor
+fn position_bonus(text: &[char], id: usize) -> i64 {
+    if id == 0 {
+        return BONUS_BOUNDARY;
+    }
+    let (previous, current) = (text[id - 1], text[id]);
+    if is_separator(previous) {
+        BONUS_SEPARATOR
+    } else if matches!(previous, ' ' | '_' | '-' | '.') {
+        BONUS_BOUNDARY
+    } else if previous.is_lowercase() && current.is_uppercase()
+        || !previous.is_ascii_digit() && current.is_ascii_digit()
+    {
+        BONUS_CAMEL_CASE
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match_subsequence() {
+        let result = fuzzy_match("tbk", "src/telescope_backend.rs").unwrap();
+        assert_eq!(result.positions, vec![4, 14, 17]);
+        assert_eq!(fuzzy_match("xyz", "src/telescope_backend.rs"), None);
+        assert_eq!(fuzzy_match("", "anything"), Some(FuzzyMatch::default()));
+    }
+
+    #[test]
+    fn test_substring_positions() {
+        assert_eq!(
+            substring_positions("Tele", "src/telescope.rs"),
+            Some(vec![4, 5, 6, 7])
+        );
+        assert_eq!(substring_positions("tbk", "src/telescope_backend.rs"), None);
+        assert_eq!(substring_positions("", "src"), Some(vec![]));
+    }
+
+    #[test]
+    fn test_fuzzy_match_ignores_case() {
+        let result = fuzzy_match("README", "docs/readme.md").unwrap();
+        assert_eq!(result.positions, vec![5, 6, 7, 8, 9, 10]);
+        assert_eq!(
+            fuzzy_match_with_case("README", "docs/readme.md", false),
+            None
+        );
+        assert_eq!(
+            substring_positions_with_case("Tele", "src/telescope.rs", false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_contiguous_runs() {
+        let contiguous = fuzzy_match("lib", "src/lib.rs").unwrap();
+        let scattered = fuzzy_match("lib", "src/l_i_b.rs").unwrap();
+        assert!(contiguous.score > scattered.score);
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_basename() {
+        let basename = fuzzy_match("sfs", "src/sfs_telescope.rs").unwrap();
+        let directory = fuzzy_match("sfs", "sfs/telescope.rs").unwrap();
+        assert!(basename.score > directory.score);
+    }
+
+    #[test]
+    fn test_score_positions_agrees_with_fuzzy_match() {
+        for (query, text) in [("tbk", "src/telescope_backend.rs"), ("lib", "src/l_i_b.rs")] {
+            let result = fuzzy_match(query, text).unwrap();
+            assert_eq!(score_positions(text, &result.positions), result.score);
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_word_boundaries() {
+        let boundary = fuzzy_match("tb", "telescope_backend").unwrap();
+        assert_eq!(boundary.positions, vec![0, 10]);
+    }
+}
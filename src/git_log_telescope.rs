@@ -0,0 +1,441 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use chrono::DateTime;
+use color_eyre::eyre::Result;
+use git2::{Diff, DiffStatsFormat, Oid, Repository, Sort};
+use ratatui::{
+    layout::Rect,
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use blaze_explorer_lib::{
+    action::{Action, AppAction},
+    app_context::AppContext,
+};
+
+use crate::{
+    config::SearchConfig,
+    extended_query::{ExtendedQuery, MatchMode},
+    search_worker::SearchHandle,
+    telescope_backend::{
+        clamp_scroll, HighlightedText, ResultRow, TelescopeResult, TelescopeSearch,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitLogSearch {
+    directory: PathBuf,
+    max_commits: usize,
+    workdir: Option<PathBuf>,
+    //Commits read by the first search, as walking a long history can take a while
+    commits: Option<Arc<Vec<GitLogResult>>>,
+    results: Vec<GitLogResult>,
+    query: String,
+    error: Option<String>,
+    //Whether the history goes on past the commits which were read
+    truncated: bool,
+}
+
+impl GitLogSearch {
+    pub fn new(ctx: AppContext, options: SearchConfig) -> Self {
+        Self {
+            directory: ctx.current_directory,
+            max_commits: options.max_results,
+            workdir: None,
+            commits: None,
+            results: Vec::new(),
+            query: String::new(),
+            error: None,
+            truncated: false,
+        }
+    }
+
+    /// The commits of the repository, read on the first search. None if the search was cancelled
+    /// while reading them.
+    fn load_commits(&mut self, handle: &SearchHandle) -> Option<Arc<Vec<GitLogResult>>> {
+        if let Some(commits) = &self.commits {
+            return Some(commits.clone());
+        }
+        let commits = match read_commits(&self.directory, self.max_commits, handle) {
+            Ok(None) => return None,
+            Ok(Some((workdir, commits, truncated))) => {
+                self.workdir = Some(workdir);
+                self.error = None;
+                self.truncated = truncated;
+                commits
+            }
+            Err(e) => {
+                self.error = Some(e.message().to_string());
+                Vec::new()
+            }
+        };
+        let commits = Arc::new(commits);
+        self.commits = Some(commits.clone());
+        Some(commits)
+    }
+}
+
+/// Working directory of a repository, the commits read from it and whether there were more
+type ReadCommits = (PathBuf, Vec<GitLogResult>, bool);
+
+/// Walk the history of the repository containing the given directory, starting at HEAD. Returns
+/// the working directory of the repository together with at most `max_commits` commits, and
+/// whether there were more, or None if the search was cancelled during the walk
+fn read_commits(
+    directory: &Path,
+    max_commits: usize,
+    handle: &SearchHandle,
+) -> Result<Option<ReadCommits>, git2::Error> {
+    let repo = Repository::discover(directory)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    let mut commits = Vec::new();
+    for oid in revwalk.by_ref().take(max_commits) {
+        if handle.is_cancelled() {
+            return Ok(None);
+        }
+        let commit = repo.find_commit(oid?)?;
+        commits.push(GitLogResult {
+            hash: commit.id().to_string(),
+            author: commit.author().name().unwrap_or("").to_string(),
+            summary: commit.summary().unwrap_or("").to_string(),
+            message: commit.message().unwrap_or("").to_string(),
+            time: commit.time().seconds(),
+            repository_path: repo.path().to_path_buf(),
+            stat: Arc::default(),
+        });
+    }
+    let truncated = revwalk.next().is_some();
+    let workdir = repo.workdir().unwrap_or(repo.path()).to_path_buf();
+    Ok(Some((workdir, commits, truncated)))
+}
+
+impl TelescopeSearch for GitLogSearch {
+    fn search(&mut self, query: String, handle: &SearchHandle) {
+        let commits = match self.load_commits(handle) {
+            Some(commits) => commits,
+            None => return,
+        };
+        let parsed = ExtendedQuery::parse(&query).unwrap_or_default();
+        self.query = query;
+        self.results = commits
+            .iter()
+            .filter(|c| {
+                parsed
+                    .matches(&c.display(), MatchMode::Substring, true)
+                    .is_some()
+            })
+            .cloned()
+            .collect();
+    }
+
+    fn confirm_result(&mut self, id: usize) -> Option<Action> {
+        let result = &self.results[id];
+        match result.first_touched_file() {
+            Ok(Some(path)) => Some(Action::AppAct(AppAction::ShowInFolder(path))),
+            Ok(None) => Some(Action::AppAct(AppAction::DisplayMessage(format!(
+                "Commit {} does not touch any files",
+                result.short_hash()
+            )))),
+            Err(e) => Some(Action::AppAct(AppAction::DisplayMessage(
+                e.message().to_string(),
+            ))),
+        }
+    }
+
+    fn get_result_rows(&self) -> Vec<ResultRow> {
+        self.results
+            .iter()
+            .map(|r| HighlightedText::from_query(r.display(), &self.query))
+            .map(ResultRow::from)
+            .collect::<Vec<ResultRow>>()
+    }
+
+    fn display(&self) -> String {
+        match (&self.workdir, &self.error) {
+            (_, Some(error)) => format!("Git commits - {}", error),
+            (Some(path), None) => format!("Git commits - {}", path.display()),
+            (None, None) => "Git commits".to_string(),
+        }
+    }
+
+    fn preview_result(
+        &self,
+        some_id: Option<usize>,
+        scroll: u16,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Result<u16> {
+        let preview_block = Block::default().borders(Borders::ALL).title("Preview");
+        match some_id {
+            Some(id) => return self.results[id].preview(frame, area, preview_block, scroll),
+            None => {
+                frame.render_widget(Paragraph::default().block(preview_block), area);
+            }
+        };
+        Ok(0)
+    }
+
+    fn n_results(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Whether the history goes on past the commits which were read. Every match among them is
+    /// shown, so the query can't bring in the ones left out.
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn truncation_note(&self) -> String {
+        format!("only the latest {} commits are searched", self.max_commits)
+    }
+
+    fn refresh(&mut self) {
+        self.commits = None;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GitLogResult {
+    hash: String,
+    author: String,
+    summary: String,
+    message: String,
+    time: i64,
+    repository_path: PathBuf,
+    //The diff stat shown in the preview, computed once per commit as the preview is drawn on
+    //every frame. Shared by the clones of the result.
+    stat: Arc<OnceLock<String>>,
+}
+
+impl GitLogResult {
+    fn short_hash(&self) -> &str {
+        &self.hash[..self.hash.len().min(7)]
+    }
+
+    /// Run the given function on the diff between the commit and its first parent
+    fn with_diff<T>(
+        &self,
+        f: impl FnOnce(&Repository, &Diff) -> Result<T, git2::Error>,
+    ) -> Result<T, git2::Error> {
+        let repo = Repository::open(&self.repository_path)?;
+        let commit = repo.find_commit(Oid::from_str(&self.hash)?)?;
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+        f(&repo, &diff)
+    }
+
+    fn first_touched_file(&self) -> Result<Option<PathBuf>, git2::Error> {
+        self.with_diff(|repo, diff| {
+            let workdir = repo.workdir().unwrap_or(Path::new(""));
+            Ok(diff.deltas().find_map(|delta| {
+                delta
+                    .new_file()
+                    .path()
+                    .or(delta.old_file().path())
+                    .map(|p| workdir.join(p))
+            }))
+        })
+    }
+
+    fn diff_stat(&self) -> Result<String, git2::Error> {
+        self.with_diff(|_, diff| {
+            let buf = diff.stats()?.to_buf(DiffStatsFormat::FULL, 80)?;
+            Ok(buf.as_str().unwrap_or("").to_string())
+        })
+    }
+}
+
+impl TelescopeResult for GitLogResult {
+    fn display(&self) -> String {
+        format!("{} {} ({})", self.short_hash(), self.summary, self.author)
+    }
+
+    fn preview(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        preview_block: Block,
+        scroll: u16,
+    ) -> Result<u16> {
+        //Render the full commit message followed by the files it changed
+        let date = DateTime::from_timestamp(self.time, 0)
+            .map(|d| d.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let stat = self.stat.get_or_init(|| {
+            self.diff_stat()
+                .unwrap_or_else(|e| format!("Could not compute the diff: {}", e.message()))
+        });
+        let mut lines = vec![
+            Line::from(format!("commit {}", self.hash)),
+            Line::from(format!("Author: {}", self.author)),
+            Line::from(format!("Date:   {}", date)),
+            Line::from(""),
+        ];
+        lines.extend(
+            self.message
+                .lines()
+                .map(|l| Line::from(format!("    {}", l))),
+        );
+        lines.push(Line::from(""));
+        lines.extend(stat.lines().map(|l| Line::from(l.to_string())));
+        let scroll = clamp_scroll(scroll, lines.len(), area);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(preview_block)
+            .scroll((scroll, 0));
+
+        frame.render_widget(paragraph, area);
+        Ok(scroll)
+    }
+
+    fn from<S>(s: S) -> Self
+    where
+        S: ToString + Display,
+    {
+        Self {
+            hash: s.to_string(),
+            author: String::new(),
+            summary: String::new(),
+            message: String::new(),
+            time: 0,
+            repository_path: PathBuf::new(),
+            stat: Arc::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::TelescopeConfig, telescope_backend::TelescopeBackend};
+    use blaze_explorer_lib::app::App;
+    use git2::{Signature, Time};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A repository with a commit adding notes.txt followed by one adding README.md and changing
+    /// notes.txt
+    fn example_repository() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        let commits = [
+            ("Add notes", vec![("notes.txt", "first\n")]),
+            (
+                "Add readme",
+                vec![
+                    ("README.md", "# Example\n"),
+                    ("notes.txt", "first\nsecond\n"),
+                ],
+            ),
+        ];
+        for (id, (message, files)) in commits.iter().enumerate() {
+            for (name, contents) in files {
+                fs::write(dir.path().join(name), contents).unwrap();
+                index.add_path(Path::new(name)).unwrap();
+            }
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let time = Time::new(1_700_000_000 + id as i64 * 60, 0);
+            let signature = Signature::new("Tester", "tester@example.com", &time).unwrap();
+            let parents = repo
+                .head()
+                .ok()
+                .map(|head| head.peel_to_commit().unwrap())
+                .into_iter()
+                .collect::<Vec<_>>();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents.iter().collect::<Vec<_>>(),
+            )
+            .unwrap();
+        }
+        dir
+    }
+
+    fn git_log_search(dir: &TempDir) -> GitLogSearch {
+        let mut ctx = App::new().unwrap().get_app_context();
+        ctx.current_directory = dir.path().to_path_buf();
+        GitLogSearch::new(ctx, SearchConfig::default())
+    }
+
+    #[test]
+    fn test_commits_are_matched() {
+        let dir = example_repository();
+        let mut search = git_log_search(&dir);
+        search.search(String::new(), &SearchHandle::detached());
+        let summaries = search
+            .results
+            .iter()
+            .map(|r| r.summary.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(summaries, vec!["Add readme", "Add notes"]);
+        assert!(!search.is_truncated());
+
+        search.search("notes tester".to_string(), &SearchHandle::detached());
+        assert_eq!(search.n_results(), 1);
+        assert_eq!(search.results[0].summary, "Add notes");
+    }
+
+    #[test]
+    fn test_history_cut_off_at_the_limit() {
+        let dir = example_repository();
+        let mut ctx = App::new().unwrap().get_app_context();
+        ctx.current_directory = dir.path().to_path_buf();
+        let mut config = TelescopeConfig::default();
+        config.pickers.git_log.max_results = Some(1);
+        let mut git_log = TelescopeBackend::new_git_log(ctx, &config);
+        git_log.wait_for_search();
+        assert_eq!(
+            git_log.results_title(),
+            "Results 0 / 1 / 1 - only the latest 1 commits are searched"
+        );
+    }
+
+    #[test]
+    fn test_preview_stat_and_first_touched_file() {
+        let dir = example_repository();
+        let mut search = git_log_search(&dir);
+        search.search("readme".to_string(), &SearchHandle::detached());
+        let stat = search.results[0].diff_stat().unwrap();
+        assert!(stat.contains("README.md"));
+        assert!(stat.contains("2 files changed, 2 insertions(+)"));
+
+        let workdir = Repository::open(dir.path())
+            .unwrap()
+            .workdir()
+            .unwrap()
+            .to_path_buf();
+        assert_eq!(
+            search.confirm_result(0),
+            Some(Action::AppAct(AppAction::ShowInFolder(
+                workdir.join("README.md")
+            )))
+        );
+    }
+
+    #[test]
+    fn test_outside_of_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut search = git_log_search(&dir);
+        search.search(String::new(), &SearchHandle::detached());
+        assert_eq!(search.n_results(), 0);
+        assert!(search.display().starts_with("Git commits - "));
+        assert!(search.error.is_some());
+    }
+}
@@ -0,0 +1,496 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use color_eyre::eyre::Result;
+use git2::{DiffFormat, DiffOptions, Repository, Status, StatusOptions};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use blaze_explorer_lib::{
+    action::{Action, AppAction},
+    app_context::AppContext,
+};
+
+use crate::{
+    extended_query::{ExtendedQuery, MatchMode},
+    search_worker::SearchHandle,
+    telescope_backend::{
+        clamp_scroll, HighlightedText, ResultRow, TelescopeResult, TelescopeSearch,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitStatusSearch {
+    directory: PathBuf,
+    workdir: Option<PathBuf>,
+    //Changed files read by the first search, as reading the status of a large repository can take
+    //a while
+    entries: Option<Arc<Vec<GitStatusResult>>>,
+    results: Vec<GitStatusResult>,
+    query: String,
+    error: Option<String>,
+}
+
+impl GitStatusSearch {
+    pub fn new(ctx: AppContext) -> Self {
+        Self {
+            directory: ctx.current_directory,
+            workdir: None,
+            entries: None,
+            results: Vec::new(),
+            query: String::new(),
+            error: None,
+        }
+    }
+
+    /// The changed files of the repository, read on the first search. None if the search was
+    /// cancelled while reading them.
+    fn load_statuses(&mut self, handle: &SearchHandle) -> Option<Arc<Vec<GitStatusResult>>> {
+        if let Some(entries) = &self.entries {
+            return Some(entries.clone());
+        }
+        let entries = match read_statuses(&self.directory, handle) {
+            Ok(None) => return None,
+            Ok(Some((workdir, entries))) => {
+                self.workdir = Some(workdir);
+                self.error = None;
+                entries
+            }
+            Err(e) => {
+                self.error = Some(e.message().to_string());
+                Vec::new()
+            }
+        };
+        let entries = Arc::new(entries);
+        self.entries = Some(entries.clone());
+        Some(entries)
+    }
+}
+
+/// Working directory of a repository and its changed files
+type ReadStatuses = (PathBuf, Vec<GitStatusResult>);
+
+/// Collect the index and working tree changes of the repository containing the given directory.
+/// Returns the working directory of the repository together with the changed files, or None if
+/// the search was cancelled while collecting them
+fn read_statuses(
+    directory: &Path,
+    handle: &SearchHandle,
+) -> Result<Option<ReadStatuses>, git2::Error> {
+    let repo = Repository::discover(directory)?;
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir.to_path_buf(),
+        None => {
+            return Err(git2::Error::from_str(
+                "Bare repositories have no working tree",
+            ))
+        }
+    };
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true);
+    let statuses = repo.statuses(Some(&mut options))?;
+    let mut entries = Vec::new();
+    for entry in statuses.iter() {
+        if handle.is_cancelled() {
+            return Ok(None);
+        }
+        let status = match status_letter(entry.status()) {
+            Some(status) => status,
+            None => continue,
+        };
+        //The path of a renamed entry is its old one, the new one is in the delta
+        let rename = entry
+            .head_to_index()
+            .filter(|_| entry.status().contains(Status::INDEX_RENAMED))
+            .or(entry
+                .index_to_workdir()
+                .filter(|_| entry.status().contains(Status::WT_RENAMED)));
+        let (old_path, path) = match rename {
+            Some(delta) => (
+                delta
+                    .old_file()
+                    .path()
+                    .map(|p| p.to_string_lossy().to_string()),
+                delta
+                    .new_file()
+                    .path()
+                    .map(|p| p.to_string_lossy().to_string()),
+            ),
+            None => (None, entry.path().map(|p| p.to_string())),
+        };
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+        entries.push(GitStatusResult {
+            status,
+            path,
+            old_path,
+            workdir: workdir.clone(),
+            repository_path: repo.path().to_path_buf(),
+            diff: DiffCache::default(),
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Some((workdir, entries)))
+}
+
+/// Summarise the status flags of a file as a single letter, similar to `git status --short`
+fn status_letter(status: Status) -> Option<char> {
+    if status.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED) {
+        Some('R')
+    } else if status.intersects(Status::INDEX_DELETED | Status::WT_DELETED) {
+        Some('D')
+    } else if status.contains(Status::INDEX_NEW) {
+        Some('A')
+    } else if status.contains(Status::WT_NEW) {
+        Some('?')
+    } else if status.intersects(
+        Status::INDEX_MODIFIED
+            | Status::WT_MODIFIED
+            | Status::INDEX_TYPECHANGE
+            | Status::WT_TYPECHANGE,
+    ) {
+        Some('M')
+    } else {
+        None
+    }
+}
+
+impl TelescopeSearch for GitStatusSearch {
+    fn search(&mut self, query: String, handle: &SearchHandle) {
+        let entries = match self.load_statuses(handle) {
+            Some(entries) => entries,
+            None => return,
+        };
+        let parsed = ExtendedQuery::parse(&query).unwrap_or_default();
+        self.query = query;
+        self.results = entries
+            .iter()
+            .filter(|e| {
+                parsed
+                    .matches(&e.label(), MatchMode::Substring, true)
+                    .is_some()
+            })
+            .cloned()
+            .collect();
+    }
+
+    fn confirm_result(&mut self, id: usize) -> Option<Action> {
+        let result = &self.results[id];
+        let path = result.workdir.join(&result.path);
+        Some(Action::AppAct(AppAction::ShowInFolder(path)))
+    }
+
+    fn get_result_rows(&self) -> Vec<ResultRow> {
+        self.results
+            .iter()
+            .map(|r| {
+                //The path follows the status letter and two spaces
+                let positions = ExtendedQuery::parse(&self.query)
+                    .ok()
+                    .and_then(|query| query.matches(&r.label(), MatchMode::Substring, true))
+                    .map(|found| found.positions)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|p| p + 3)
+                    .collect();
+                HighlightedText::new(r.display(), positions)
+            })
+            .map(ResultRow::from)
+            .collect::<Vec<ResultRow>>()
+    }
+
+    fn display(&self) -> String {
+        match (&self.workdir, &self.error) {
+            (_, Some(error)) => format!("Git status - {}", error),
+            (Some(path), None) => format!("Git status - {}", path.display()),
+            (None, None) => "Git status".to_string(),
+        }
+    }
+
+    fn preview_result(
+        &self,
+        some_id: Option<usize>,
+        scroll: u16,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Result<u16> {
+        let preview_block = Block::default().borders(Borders::ALL).title("Preview");
+        match some_id {
+            Some(id) => return self.results[id].preview(frame, area, preview_block, scroll),
+            None => {
+                frame.render_widget(Paragraph::default().block(preview_block), area);
+            }
+        };
+        Ok(0)
+    }
+
+    fn n_results(&self) -> usize {
+        self.results.len()
+    }
+
+    fn get_path(&self, id: usize) -> Option<PathBuf> {
+        self.results.get(id).map(|r| r.workdir.join(&r.path))
+    }
+
+    fn refresh(&mut self) {
+        self.entries = None;
+    }
+}
+
+/// Modification times and size telling whether the diff of a file may have changed: staging
+/// rewrites the index, and editing the file changes its own metadata
+#[derive(Debug, Clone, PartialEq)]
+struct DiffVersion {
+    file_modified: Option<SystemTime>,
+    file_size: Option<u64>,
+    index_modified: Option<SystemTime>,
+}
+
+/// The last diff computed for the preview, kept as the preview is drawn on every frame. Shared by
+/// the clones of the result.
+#[derive(Debug, Clone, Default)]
+struct DiffCache(Arc<Mutex<Option<CachedDiff>>>);
+
+type CachedDiff = (DiffVersion, Vec<Line<'static>>);
+
+impl PartialEq for DiffCache {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct GitStatusResult {
+    status: char,
+    path: String,
+    //Path of a renamed file before the rename
+    old_path: Option<String>,
+    workdir: PathBuf,
+    repository_path: PathBuf,
+    diff: DiffCache,
+}
+
+impl GitStatusResult {
+    /// The path, preceded by the old one for renamed files
+    fn label(&self) -> String {
+        match &self.old_path {
+            Some(old_path) => format!("{} -> {}", old_path, self.path),
+            None => self.path.clone(),
+        }
+    }
+
+    fn diff_version(&self) -> DiffVersion {
+        let file = fs::symlink_metadata(self.workdir.join(&self.path)).ok();
+        let index = fs::metadata(self.repository_path.join("index")).ok();
+        DiffVersion {
+            file_modified: file.as_ref().and_then(|m| m.modified().ok()),
+            file_size: file.map(|m| m.len()),
+            index_modified: index.and_then(|m| m.modified().ok()),
+        }
+    }
+
+    /// The diff of the file, computed again only when the file or the index changed
+    fn cached_diff_lines(&self) -> Vec<Line<'static>> {
+        let version = self.diff_version();
+        let mut cache = self
+            .diff
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((cached_version, lines)) = cache.as_ref() {
+            if *cached_version == version {
+                return lines.clone();
+            }
+        }
+        let lines = self.diff_lines().unwrap_or_else(|e| {
+            vec![Line::from(format!(
+                "Could not compute the diff: {}",
+                e.message()
+            ))]
+        });
+        *cache = Some((version, lines.clone()));
+        lines
+    }
+
+    /// Produce the unified diff of the file between HEAD and the working tree, covering both the
+    /// staged and the unstaged changes
+    fn diff_lines(&self) -> Result<Vec<Line<'static>>, git2::Error> {
+        let repo = Repository::open(&self.repository_path)?;
+        let head_tree = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(_) => None,
+        };
+        let mut options = DiffOptions::new();
+        options.pathspec(&self.path);
+        if let Some(old_path) = &self.old_path {
+            options.pathspec(old_path);
+        }
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        let mut diff =
+            repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut options))?;
+        //pair the old and the new path of renamed files
+        diff.find_similar(None)?;
+        let mut lines = Vec::new();
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            let content = String::from_utf8_lossy(line.content())
+                .trim_end_matches('\n')
+                .to_string();
+            let styled_line = match line.origin() {
+                '+' => Line::styled(format!("+{}", content), Style::default().fg(Color::Green)),
+                '-' => Line::styled(format!("-{}", content), Style::default().fg(Color::Red)),
+                ' ' => Line::from(format!(" {}", content)),
+                'H' => Line::styled(content, Style::default().fg(Color::Cyan)),
+                _ => Line::from(content),
+            };
+            lines.push(styled_line);
+            true
+        })?;
+        Ok(lines)
+    }
+}
+
+impl TelescopeResult for GitStatusResult {
+    fn display(&self) -> String {
+        format!("{}  {}", self.status, self.label())
+    }
+
+    fn preview(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        preview_block: Block,
+        scroll: u16,
+    ) -> Result<u16> {
+        //Render the diff of the file rather than its contents
+        let lines = self.cached_diff_lines();
+        let scroll = clamp_scroll(scroll, lines.len(), area);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(preview_block)
+            .scroll((scroll, 0));
+
+        frame.render_widget(paragraph, area);
+        Ok(scroll)
+    }
+
+    fn from<S>(s: S) -> Self
+    where
+        S: ToString + Display,
+    {
+        Self {
+            status: ' ',
+            path: s.to_string(),
+            old_path: None,
+            workdir: PathBuf::new(),
+            repository_path: PathBuf::new(),
+            diff: DiffCache::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blaze_explorer_lib::app::App;
+
+    #[test]
+    fn test_status_letter() {
+        assert_eq!(status_letter(Status::WT_NEW), Some('?'));
+        assert_eq!(status_letter(Status::INDEX_NEW), Some('A'));
+        assert_eq!(
+            status_letter(Status::INDEX_NEW | Status::WT_MODIFIED),
+            Some('A')
+        );
+        assert_eq!(status_letter(Status::WT_MODIFIED), Some('M'));
+        assert_eq!(status_letter(Status::WT_DELETED), Some('D'));
+        assert_eq!(status_letter(Status::INDEX_RENAMED), Some('R'));
+        assert_eq!(status_letter(Status::IGNORED), None);
+    }
+
+    #[test]
+    fn test_diff_is_cached_until_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, "first\n").unwrap();
+        let (_, entries) = read_statuses(dir.path(), &SearchHandle::detached())
+            .unwrap()
+            .unwrap();
+        let entry = &entries[0];
+        let contents = |lines: Vec<Line>| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        assert!(contents(entry.cached_diff_lines()).contains(&"+first".to_string()));
+        let cached = entry.diff.0.lock().unwrap().clone().unwrap();
+        assert_eq!(cached.0, entry.diff_version());
+
+        fs::write(&file, "first\nsecond\n").unwrap();
+        assert!(contents(entry.cached_diff_lines()).contains(&"+second".to_string()));
+    }
+
+    #[test]
+    fn test_renamed_file_shows_both_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("old.txt"), "renamed contents\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("old.txt")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("Tester", "tester@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "Add", &tree, &[])
+            .unwrap();
+
+        fs::rename(dir.path().join("old.txt"), dir.path().join("new.txt")).unwrap();
+        index.remove_path(Path::new("old.txt")).unwrap();
+        index.add_path(Path::new("new.txt")).unwrap();
+        index.write().unwrap();
+
+        let (_, entries) = read_statuses(dir.path(), &SearchHandle::detached())
+            .unwrap()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].display(), "R  old.txt -> new.txt");
+        assert_eq!(entries[0].path, "new.txt");
+        let diff = entries[0].cached_diff_lines();
+        assert!(diff
+            .iter()
+            .any(|l| l.to_string().contains("rename to new.txt")));
+    }
+
+    #[test]
+    fn test_statuses_are_read_again_on_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join("first.txt"), "first\n").unwrap();
+        let mut ctx = App::new().unwrap().get_app_context();
+        ctx.current_directory = dir.path().to_path_buf();
+        let mut search = GitStatusSearch::new(ctx);
+        let handle = SearchHandle::detached();
+        search.search(String::new(), &handle);
+        assert_eq!(search.n_results(), 1);
+
+        fs::write(dir.path().join("second.txt"), "second\n").unwrap();
+        search.search(String::new(), &handle);
+        assert_eq!(search.n_results(), 1);
+        search.refresh();
+        search.search(String::new(), &handle);
+        assert_eq!(search.n_results(), 2);
+    }
+}
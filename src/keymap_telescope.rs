@@ -0,0 +1,233 @@
+use std::{collections::HashMap, fmt::Display};
+
+use color_eyre::eyre::Result;
+use ratatui::{
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    layout::Rect,
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use blaze_explorer_lib::{action::Action, mode::Mode};
+
+use crate::{
+    extended_query::{ExtendedQuery, MatchMode},
+    get_functionalities,
+    search_worker::SearchHandle,
+    telescope_backend::{
+        clamp_scroll, HighlightedText, ResultRow, TelescopeResult, TelescopeSearch,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeymapSearch {
+    keymaps: Vec<KeymapResult>,
+    results: Vec<KeymapResult>,
+    query: String,
+}
+
+impl KeymapSearch {
+    pub fn new(bindings: HashMap<(Mode, Vec<KeyEvent>), String>) -> Self {
+        let mut keymaps = bindings
+            .into_iter()
+            .map(|((mode, events), functionality)| {
+                KeymapResult::new(
+                    format!("{:?}", mode),
+                    key_events_to_string(&events),
+                    functionality,
+                )
+            })
+            .collect::<Vec<KeymapResult>>();
+        keymaps.sort_by(|a, b| (&a.mode, &a.keys).cmp(&(&b.mode, &b.keys)));
+        Self {
+            results: keymaps.clone(),
+            keymaps,
+            query: String::new(),
+        }
+    }
+
+    /// Width of the mode and key sequence columns, so that the entries line up
+    fn column_widths(&self) -> (usize, usize) {
+        self.keymaps.iter().fold((0, 0), |(mode, keys), k| {
+            (mode.max(k.mode.len()), keys.max(k.keys.chars().count()))
+        })
+    }
+}
+
+impl TelescopeSearch for KeymapSearch {
+    fn search(&mut self, query: String, _handle: &SearchHandle) {
+        let parsed = ExtendedQuery::parse(&query).unwrap_or_default();
+        self.query = query;
+        self.results = self
+            .keymaps
+            .iter()
+            .filter(|k| {
+                let text = format!("{} {} {}", k.mode, k.keys, k.functionality);
+                parsed.matches(&text, MatchMode::Substring, true).is_some()
+            })
+            .cloned()
+            .collect();
+    }
+
+    fn confirm_result(&mut self, id: usize) -> Option<Action> {
+        //Run the action bound to the keymap, the same way the key sequence would
+        get_functionalities()
+            .get(&self.results[id].functionality)
+            .cloned()
+    }
+
+    fn get_result_rows(&self) -> Vec<ResultRow> {
+        let (mode_width, keys_width) = self.column_widths();
+        self.results
+            .iter()
+            .map(|k| {
+                let text = format!(
+                    "{:<mode_width$}  {:<keys_width$}  {}",
+                    k.mode, k.keys, k.functionality
+                );
+                HighlightedText::from_query(text, &self.query)
+            })
+            .map(ResultRow::from)
+            .collect::<Vec<ResultRow>>()
+    }
+
+    fn display(&self) -> String {
+        "Plugin keymaps".to_string()
+    }
+
+    fn preview_result(
+        &self,
+        some_id: Option<usize>,
+        scroll: u16,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Result<u16> {
+        let preview_block = Block::default().borders(Borders::ALL).title("Preview");
+        match some_id {
+            Some(id) => return self.results[id].preview(frame, area, preview_block, scroll),
+            None => {
+                frame.render_widget(Paragraph::default().block(preview_block), area);
+            }
+        };
+        Ok(0)
+    }
+
+    fn n_results(&self) -> usize {
+        self.results.len()
+    }
+}
+
+/// Turn a sequence of key events back into the notation used when defining bindings
+pub fn key_events_to_string(events: &[KeyEvent]) -> String {
+    events
+        .iter()
+        .map(|event| {
+            let key = match event.code {
+                KeyCode::Char(' ') => "Space".to_string(),
+                KeyCode::Char(ch) => ch.to_string(),
+                KeyCode::Enter => "CR".to_string(),
+                KeyCode::Esc => "Esc".to_string(),
+                KeyCode::Backspace => "BS".to_string(),
+                KeyCode::Tab => "Tab".to_string(),
+                other => format!("{:?}", other),
+            };
+            let prefix = match event.modifiers {
+                m if m.contains(KeyModifiers::CONTROL) => "C-",
+                m if m.contains(KeyModifiers::ALT) => "A-",
+                _ => "",
+            };
+            match (prefix, key.chars().count()) {
+                ("", 1) => key,
+                _ => format!("<{}{}>", prefix, key),
+            }
+        })
+        .collect::<String>()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct KeymapResult {
+    mode: String,
+    keys: String,
+    functionality: String,
+}
+
+impl KeymapResult {
+    pub fn new(mode: String, keys: String, functionality: String) -> Self {
+        Self {
+            mode,
+            keys,
+            functionality,
+        }
+    }
+}
+
+impl TelescopeResult for KeymapResult {
+    fn display(&self) -> String {
+        format!("{} {} {}", self.mode, self.keys, self.functionality)
+    }
+
+    fn preview(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        preview_block: Block,
+        scroll: u16,
+    ) -> Result<u16> {
+        let lines = vec![
+            Line::from(format!("Functionality: {}", self.functionality)),
+            Line::from(format!("Mode: {}", self.mode)),
+            Line::from(format!("Keys: {}", self.keys)),
+        ];
+        let scroll = clamp_scroll(scroll, lines.len(), area);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(preview_block)
+            .scroll((scroll, 0));
+
+        frame.render_widget(paragraph, area);
+        Ok(scroll)
+    }
+
+    fn from<S>(s: S) -> Self
+    where
+        S: ToString + Display,
+    {
+        Self::new(String::new(), String::new(), s.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use blaze_explorer_lib::input_machine::input_machine_helpers::convert_str_to_events;
+
+    use super::*;
+
+    #[test]
+    fn test_key_events_to_string() {
+        assert_eq!(
+            key_events_to_string(&convert_str_to_events(" sg")),
+            "<Space>sg"
+        );
+        assert_eq!(
+            key_events_to_string(&convert_str_to_events("<C-n>")),
+            "<C-n>"
+        );
+        assert_eq!(key_events_to_string(&convert_str_to_events("<CR>")), "<CR>");
+    }
+
+    #[test]
+    fn test_confirm_keymap_runs_functionality() {
+        let mut bindings = HashMap::new();
+        bindings.insert(
+            (Mode::PopUp, convert_str_to_events("<Esc>")),
+            "TelescopeQuit".to_string(),
+        );
+        let mut search = KeymapSearch::new(bindings);
+        search.search("quit".to_string(), &SearchHandle::detached());
+        assert_eq!(search.n_results(), 1);
+        assert_eq!(
+            search.confirm_result(0),
+            get_functionalities().get("TelescopeQuit").cloned()
+        );
+    }
+}
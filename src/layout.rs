@@ -0,0 +1,168 @@
+//Layout of the Telescope popup, following the layout strategies of telescope.nvim
+
+use blaze_explorer_lib::tools::center_rect;
+use ratatui::layout::{Constraint, Layout, Rect};
+use serde::Deserialize;
+
+//Height of the prompt, including its borders
+const PROMPT_HEIGHT: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutStrategy {
+    /// Results on the left, preview on the right
+    #[default]
+    Horizontal,
+    /// Preview above the results
+    Vertical,
+    /// Narrow popup with the results only
+    Dropdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptPosition {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// Sizes are percentages of the terminal (for the popup) or of the popup (for the preview)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub strategy: LayoutStrategy,
+    pub prompt_position: PromptPosition,
+    pub width: u16,
+    pub height: u16,
+    pub dropdown_width: u16,
+    pub preview_size: u16,
+    /// Terminals narrower than this number of columns use the vertical layout instead of the
+    /// horizontal one
+    pub flip_columns: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            strategy: LayoutStrategy::default(),
+            prompt_position: PromptPosition::default(),
+            width: 80,
+            height: 80,
+            dropdown_width: 50,
+            preview_size: 40,
+            flip_columns: 100,
+        }
+    }
+}
+
+/// Areas of the popup parts. There is no preview area in the dropdown layout.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PopupAreas {
+    pub popup: Rect,
+    pub results: Rect,
+    pub prompt: Rect,
+    pub preview: Option<Rect>,
+}
+
+impl LayoutConfig {
+    /// Strategy actually used for a terminal of the given width
+    pub fn effective_strategy(&self, terminal_width: u16) -> LayoutStrategy {
+        match self.strategy {
+            LayoutStrategy::Horizontal if terminal_width < self.flip_columns => {
+                LayoutStrategy::Vertical
+            }
+            strategy => strategy,
+        }
+    }
+
+    pub fn split(&self, area: Rect) -> PopupAreas {
+        let strategy = self.effective_strategy(area.width);
+        let width = match strategy {
+            LayoutStrategy::Dropdown => self.dropdown_width,
+            _ => self.width,
+        };
+        let popup = center_rect(
+            area,
+            Constraint::Percentage(width.min(100)),
+            Constraint::Percentage(self.height.min(100)),
+        );
+        let preview_size = self.preview_size.min(100);
+        let (list_area, preview) = match strategy {
+            LayoutStrategy::Horizontal => {
+                let chunks = Layout::horizontal([
+                    Constraint::Percentage(100 - preview_size),
+                    Constraint::Percentage(preview_size),
+                ])
+                .split(popup);
+                (chunks[0], Some(chunks[1]))
+            }
+            LayoutStrategy::Vertical => {
+                let chunks = Layout::vertical([
+                    Constraint::Percentage(preview_size),
+                    Constraint::Percentage(100 - preview_size),
+                ])
+                .split(popup);
+                (chunks[1], Some(chunks[0]))
+            }
+            LayoutStrategy::Dropdown => (popup, None),
+        };
+        let (results, prompt) = match self.prompt_position {
+            PromptPosition::Top => {
+                let chunks =
+                    Layout::vertical([Constraint::Length(PROMPT_HEIGHT), Constraint::Fill(1)])
+                        .split(list_area);
+                (chunks[1], chunks[0])
+            }
+            PromptPosition::Bottom => {
+                let chunks =
+                    Layout::vertical([Constraint::Fill(1), Constraint::Length(PROMPT_HEIGHT)])
+                        .split(list_area);
+                (chunks[0], chunks[1])
+            }
+        };
+        PopupAreas {
+            popup,
+            results,
+            prompt,
+            preview,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_horizontal_layout() {
+        let areas = LayoutConfig::default().split(Rect::new(0, 0, 200, 50));
+        let preview = areas.preview.unwrap();
+        assert!(preview.x > areas.results.x);
+        assert_eq!(areas.prompt.height, PROMPT_HEIGHT);
+        assert!(areas.prompt.y > areas.results.y);
+    }
+
+    #[test]
+    fn test_narrow_terminal_uses_vertical_layout() {
+        let config = LayoutConfig::default();
+        assert_eq!(config.effective_strategy(80), LayoutStrategy::Vertical);
+        let areas = config.split(Rect::new(0, 0, 80, 50));
+        let preview = areas.preview.unwrap();
+        assert_eq!(preview.x, areas.results.x);
+        assert!(preview.y < areas.results.y);
+    }
+
+    #[test]
+    fn test_dropdown_layout_with_prompt_on_top() {
+        let config = LayoutConfig {
+            strategy: LayoutStrategy::Dropdown,
+            prompt_position: PromptPosition::Top,
+            ..LayoutConfig::default()
+        };
+        let areas = config.split(Rect::new(0, 0, 200, 50));
+        assert_eq!(areas.preview, None);
+        assert_eq!(areas.popup.width, 100);
+        assert!(areas.prompt.y < areas.results.y);
+    }
+}
@@ -1,4 +1,21 @@
+pub mod batch_actions;
+pub mod config;
+pub mod directory_tree;
+pub mod extended_query;
+pub mod file_columns;
+pub mod file_preview;
+pub mod file_query;
+pub mod file_walk;
+pub mod fuzzy;
+pub mod git_log_telescope;
+pub mod git_status_telescope;
+pub mod keymap_telescope;
+pub mod layout;
+pub mod live_grep_telescope;
+pub mod path_display;
+pub mod search_worker;
 pub mod sfs_telescope;
+pub mod syntax_highlight;
 pub mod telescope_backend;
 pub mod telescope_commands;
 
@@ -10,9 +27,17 @@ use blaze_explorer_lib::plugin::plugin_helpers::get_push_on_char_action;
 use blaze_explorer_lib::{create_plugin_action, insert_binding};
 
 use ratatui::crossterm::event::KeyCode;
-use telescope_commands::open_sfs;
+use telescope_commands::{
+    open_git_log, open_git_status, open_keymaps, open_live_grep, open_sfs, take_window_commands,
+    telescope_copy_paths, telescope_cycle_query_mode, telescope_cycle_sort,
+    telescope_delete_marked, telescope_move_marked, telescope_preview_half_page_down,
+    telescope_preview_half_page_up, telescope_preview_scroll_down, telescope_preview_scroll_up,
+    telescope_preview_top, telescope_refresh, telescope_toggle_hidden, telescope_toggle_ignored,
+    telescope_toggle_mark, WindowCommand, WINDOW_DETAILS,
+};
 
 use color_eyre::eyre::Result;
+use config::TelescopeConfig;
 use ratatui::{crossterm::event::KeyEvent, layout::Rect, widgets::Clear, Frame};
 use std::collections::HashMap;
 use telescope_backend::TelescopeBackend;
@@ -56,6 +81,10 @@ pub extern "Rust" fn get_plugin(
 pub fn get_functionalities() -> HashMap<String, Action> {
     let mut functionality_map = HashMap::new();
     functionality_map.insert("OpenSFS".to_string(), custom_action!(open_sfs));
+    functionality_map.insert("OpenLiveGrep".to_string(), custom_action!(open_live_grep));
+    functionality_map.insert("OpenKeymaps".to_string(), custom_action!(open_keymaps));
+    functionality_map.insert("OpenGitLog".to_string(), custom_action!(open_git_log));
+    functionality_map.insert("OpenGitStatus".to_string(), custom_action!(open_git_status));
     functionality_map.insert(
         "TelescopeQuit".to_string(),
         create_plugin_action!(PluginQuit),
@@ -76,6 +105,62 @@ pub fn get_functionalities() -> HashMap<String, Action> {
         "TelescopeConfirmResult".to_string(),
         create_plugin_action!(PluginConfirmResult),
     );
+    functionality_map.insert(
+        "TelescopeRefresh".to_string(),
+        custom_action!(telescope_refresh),
+    );
+    functionality_map.insert(
+        "TelescopeToggleMark".to_string(),
+        custom_action!(telescope_toggle_mark),
+    );
+    functionality_map.insert(
+        "TelescopeCopyPaths".to_string(),
+        custom_action!(telescope_copy_paths),
+    );
+    functionality_map.insert(
+        "TelescopeDeleteMarked".to_string(),
+        custom_action!(telescope_delete_marked),
+    );
+    functionality_map.insert(
+        "TelescopeMoveMarked".to_string(),
+        custom_action!(telescope_move_marked),
+    );
+    functionality_map.insert(
+        "TelescopePreviewScrollDown".to_string(),
+        custom_action!(telescope_preview_scroll_down),
+    );
+    functionality_map.insert(
+        "TelescopePreviewScrollUp".to_string(),
+        custom_action!(telescope_preview_scroll_up),
+    );
+    functionality_map.insert(
+        "TelescopePreviewHalfPageDown".to_string(),
+        custom_action!(telescope_preview_half_page_down),
+    );
+    functionality_map.insert(
+        "TelescopePreviewHalfPageUp".to_string(),
+        custom_action!(telescope_preview_half_page_up),
+    );
+    functionality_map.insert(
+        "TelescopePreviewTop".to_string(),
+        custom_action!(telescope_preview_top),
+    );
+    functionality_map.insert(
+        "TelescopeToggleHidden".to_string(),
+        custom_action!(telescope_toggle_hidden),
+    );
+    functionality_map.insert(
+        "TelescopeToggleIgnored".to_string(),
+        custom_action!(telescope_toggle_ignored),
+    );
+    functionality_map.insert(
+        "TelescopeCycleQueryMode".to_string(),
+        custom_action!(telescope_cycle_query_mode),
+    );
+    functionality_map.insert(
+        "TelescopeCycleSort".to_string(),
+        custom_action!(telescope_cycle_sort),
+    );
 
     functionality_map
 }
@@ -84,6 +169,10 @@ pub fn get_functionalities() -> HashMap<String, Action> {
 pub fn get_default_bindings() -> HashMap<(Mode, Vec<KeyEvent>), String> {
     let mut bindings_map = HashMap::new();
     insert_binding!(bindings_map, Mode::Normal, " sg", "OpenSFS");
+    insert_binding!(bindings_map, Mode::Normal, " sl", "OpenLiveGrep");
+    insert_binding!(bindings_map, Mode::Normal, " sk", "OpenKeymaps");
+    insert_binding!(bindings_map, Mode::Normal, " sc", "OpenGitLog");
+    insert_binding!(bindings_map, Mode::Normal, " ss", "OpenGitStatus");
     insert_binding!(bindings_map, Mode::PopUp, "<Esc>", "TelescopeQuit");
 
     insert_binding!(bindings_map, Mode::PopUp, "<C-n>", "TelescopeNextResult");
@@ -98,6 +187,48 @@ pub fn get_default_bindings() -> HashMap<(Mode, Vec<KeyEvent>), String> {
     insert_binding!(bindings_map, Mode::PopUp, "<BS>", "TelescopeDropSearchChar");
 
     insert_binding!(bindings_map, Mode::PopUp, "<CR>", "TelescopeConfirmResult");
+
+    insert_binding!(bindings_map, Mode::PopUp, "<C-r>", "TelescopeRefresh");
+
+    insert_binding!(bindings_map, Mode::PopUp, "<Tab>", "TelescopeToggleMark");
+    insert_binding!(bindings_map, Mode::PopUp, "<C-y>", "TelescopeCopyPaths");
+    insert_binding!(bindings_map, Mode::PopUp, "<C-x>", "TelescopeDeleteMarked");
+    insert_binding!(bindings_map, Mode::PopUp, "<C-o>", "TelescopeMoveMarked");
+
+    insert_binding!(
+        bindings_map,
+        Mode::PopUp,
+        "<C-j>",
+        "TelescopePreviewScrollDown"
+    );
+    insert_binding!(
+        bindings_map,
+        Mode::PopUp,
+        "<C-k>",
+        "TelescopePreviewScrollUp"
+    );
+    insert_binding!(
+        bindings_map,
+        Mode::PopUp,
+        "<C-d>",
+        "TelescopePreviewHalfPageDown"
+    );
+    insert_binding!(
+        bindings_map,
+        Mode::PopUp,
+        "<C-u>",
+        "TelescopePreviewHalfPageUp"
+    );
+    insert_binding!(bindings_map, Mode::PopUp, "<C-t>", "TelescopePreviewTop");
+    insert_binding!(bindings_map, Mode::PopUp, "<C-a>", "TelescopeToggleHidden");
+    insert_binding!(bindings_map, Mode::PopUp, "<C-g>", "TelescopeToggleIgnored");
+    insert_binding!(
+        bindings_map,
+        Mode::PopUp,
+        "<C-e>",
+        "TelescopeCycleQueryMode"
+    );
+    insert_binding!(bindings_map, Mode::PopUp, "<C-s>", "TelescopeCycleSort");
     bindings_map
 }
 
@@ -160,21 +291,86 @@ pub struct TelescopeWindow {
 }
 
 impl TelescopeWindow {
-    pub fn new_sfs(ctx: AppContext, keymap: HashMap<(Mode, Vec<KeyEvent>), Action>) -> Self {
+    pub fn new_sfs(
+        ctx: AppContext,
+        config: &TelescopeConfig,
+        keymap: HashMap<(Mode, Vec<KeyEvent>), Action>,
+    ) -> Self {
+        TelescopeWindow {
+            keymap,
+            telescope_backend: TelescopeBackend::new_sfs(ctx, config),
+            should_quit: false,
+        }
+    }
+
+    pub fn new_live_grep(
+        ctx: AppContext,
+        config: &TelescopeConfig,
+        keymap: HashMap<(Mode, Vec<KeyEvent>), Action>,
+    ) -> Self {
         TelescopeWindow {
             keymap,
-            telescope_backend: TelescopeBackend::new_sfs(ctx),
+            telescope_backend: TelescopeBackend::new_live_grep(ctx, config),
             should_quit: false,
         }
     }
 
-    fn update_self_query(&mut self) {
-        let query = self.get_search_query();
-        self.update_search_query(query);
+    pub fn new_git_log(
+        ctx: AppContext,
+        config: &TelescopeConfig,
+        keymap: HashMap<(Mode, Vec<KeyEvent>), Action>,
+    ) -> Self {
+        TelescopeWindow {
+            keymap,
+            telescope_backend: TelescopeBackend::new_git_log(ctx, config),
+            should_quit: false,
+        }
+    }
+
+    pub fn new_git_status(
+        ctx: AppContext,
+        config: &TelescopeConfig,
+        keymap: HashMap<(Mode, Vec<KeyEvent>), Action>,
+    ) -> Self {
+        TelescopeWindow {
+            keymap,
+            telescope_backend: TelescopeBackend::new_git_status(ctx, config),
+            should_quit: false,
+        }
+    }
+
+    pub fn new_keymaps(
+        ctx: AppContext,
+        config: &TelescopeConfig,
+        keymap: HashMap<(Mode, Vec<KeyEvent>), Action>,
+        bindings: HashMap<(Mode, Vec<KeyEvent>), String>,
+    ) -> Self {
+        TelescopeWindow {
+            keymap,
+            telescope_backend: TelescopeBackend::new_keymaps(ctx, config, bindings),
+            should_quit: false,
+        }
+    }
+
+    fn apply_window_commands(&mut self) {
+        for command in take_window_commands() {
+            match command {
+                WindowCommand::Refresh => self.telescope_backend.refresh(),
+                WindowCommand::ToggleMark => self.telescope_backend.toggle_mark(),
+                WindowCommand::StartBatch(batch) => self.telescope_backend.start_batch(batch),
+                WindowCommand::ScrollPreview(scroll) => {
+                    self.telescope_backend.scroll_preview(scroll)
+                }
+                WindowCommand::ToggleFilter(filter) => self.telescope_backend.toggle_filter(filter),
+                WindowCommand::CycleQueryMode => self.telescope_backend.cycle_query_mode(),
+                WindowCommand::CycleSort => self.telescope_backend.cycle_sort(),
+            }
+        }
     }
 }
 impl PluginPopUp for TelescopeWindow {
     fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
+        self.apply_window_commands();
         self.telescope_backend.draw(frame, area)?;
         Ok(())
     }
@@ -199,14 +395,12 @@ impl PluginPopUp for TelescopeWindow {
     }
 
     fn push_search_char(&mut self, ch: char) -> Option<Action> {
-        self.telescope_backend.query.append_char(ch);
-        self.update_self_query();
+        self.telescope_backend.push_char(ch);
         None
     }
 
     fn drop_search_char(&mut self) -> Option<Action> {
-        self.telescope_backend.query.drop_char();
-        self.update_self_query();
+        self.telescope_backend.drop_char();
         None
     }
 
@@ -219,8 +413,7 @@ impl PluginPopUp for TelescopeWindow {
     }
 
     fn erase_text(&mut self) -> Option<Action> {
-        self.telescope_backend.query.clear_contents();
-        self.update_self_query();
+        self.telescope_backend.erase_text();
         None
     }
 
@@ -229,7 +422,7 @@ impl PluginPopUp for TelescopeWindow {
     }
 
     fn display_details(&self) -> String {
-        "Telescope".to_string()
+        WINDOW_DETAILS.to_string()
     }
 
     fn get_default_action(&self) -> Box<fn(KeyEvent) -> Option<Action>> {
@@ -257,12 +450,26 @@ mod tests {
         assert_eq!(obtained_bindings, expected_bindings);
     }
 
+    #[test]
+    fn test_window_commands_need_a_telescope_window() {
+        let mut app = App::new().unwrap();
+        telescope_refresh(&mut app);
+        assert!(take_window_commands().is_empty());
+
+        let ctx = app.get_app_context();
+        let window = TelescopeWindow::new_sfs(ctx, &TelescopeConfig::default(), HashMap::new());
+        app.attach_popup(Box::new(window));
+        telescope_refresh(&mut app);
+        assert_eq!(take_window_commands(), vec![WindowCommand::Refresh]);
+    }
+
     #[test]
     fn test_confirm_result() {
         let mut app = App::new().unwrap();
         let ctx = app.get_app_context();
-        let mut sfs = TelescopeBackend::new_sfs(ctx);
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
         sfs.update_search_query("folder".to_string());
+        sfs.wait_for_search();
         sfs.table_state.select(Some(1));
         let resulting_action = sfs.confirm_result();
         //Get the root folder
@@ -271,4 +478,73 @@ mod tests {
         let expected_action = Some(Action::AppAct(AppAction::ShowInFolder(expected_path)));
         assert_eq!(resulting_action, expected_action);
     }
+
+    #[test]
+    fn test_fuzzy_search_ranks_best_match_first() {
+        let mut app = App::new().unwrap();
+        let mut ctx = app.get_app_context();
+        ctx.current_directory = env::current_dir().unwrap().join("tests/fuzzy_ranking");
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("tbk".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        let resulting_action = sfs.confirm_result();
+        let expected_path = env::current_dir()
+            .unwrap()
+            .join("tests/fuzzy_ranking/src/telescope_backend.rs");
+        let expected_action = Some(Action::AppAct(AppAction::ShowInFolder(expected_path)));
+        assert_eq!(resulting_action, expected_action);
+    }
+
+    #[test]
+    fn test_metadata_predicates() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("example ext:xlsx size:>1k".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        let resulting_action = sfs.confirm_result();
+        let expected_path = env::current_dir()
+            .unwrap()
+            .join("tests/folder_1/example_file.xlsx");
+        let expected_action = Some(Action::AppAct(AppAction::ShowInFolder(expected_path)));
+        assert_eq!(resulting_action, expected_action);
+        assert_eq!(sfs.search.n_results(), 1);
+    }
+
+    #[test]
+    fn test_narrowed_query_filters_cached_candidates() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("fold".to_string());
+        sfs.wait_for_search();
+        sfs.update_search_query("folder_2".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        let resulting_action = sfs.confirm_result();
+        let expected_path = env::current_dir().unwrap().join("tests/folder_2");
+        let expected_action = Some(Action::AppAct(AppAction::ShowInFolder(expected_path)));
+        assert_eq!(resulting_action, expected_action);
+    }
+
+    #[test]
+    fn test_confirm_live_grep_result() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut live_grep = TelescopeBackend::new_live_grep(ctx, &TelescopeConfig::default());
+        //Build the query so that this file does not match it
+        let query = format!("{}{}", "ZEBRA", "crossing");
+        live_grep.update_search_query(query);
+        live_grep.wait_for_search();
+        assert_eq!(live_grep.search.n_results(), 1);
+        live_grep.table_state.select(Some(0));
+        let resulting_action = live_grep.confirm_result();
+        let expected_path = env::current_dir()
+            .unwrap()
+            .join("tests/folder_1/example_text.txt");
+        let expected_action = Some(Action::AppAct(AppAction::ShowInFolder(expected_path)));
+        assert_eq!(resulting_action, expected_action);
+    }
 }
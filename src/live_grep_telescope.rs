@@ -0,0 +1,406 @@
+use std::{
+    fmt::Display,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::Result;
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use blaze_explorer_lib::{
+    action::{Action, AppAction},
+    app_context::AppContext,
+};
+
+use crate::{
+    config::SearchConfig,
+    extended_query::{MatchMode, QueryError, TextQuery},
+    file_preview::{preview_lines, special_file_kind},
+    file_walk::walk_files,
+    path_display::display_path,
+    search_worker::SearchHandle,
+    telescope_backend::{
+        clamp_scroll, HighlightedText, ResultRow, SearchFilter, TelescopeResult, TelescopeSearch,
+    },
+};
+
+//Files larger than this are not searched, as they are rarely text
+const MAX_GREP_FILE_SIZE: u64 = 16 << 20;
+//Number of lines read from a file between checks for the cancellation of the search
+const CANCEL_CHECK_LINES: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveGrepSearch {
+    absolute_directory: String,
+    options: SearchConfig,
+    query: String,
+    results: Vec<LiveGrepResult>,
+    //Number of matching lines, including the ones past the result limit
+    n_matched: usize,
+    last_search_timing: Option<Duration>,
+}
+
+impl LiveGrepSearch {
+    pub fn new(ctx: AppContext, options: SearchConfig) -> Self {
+        Self {
+            absolute_directory: ctx.current_directory.display().to_string(),
+            options,
+            query: String::new(),
+            results: Vec::new(),
+            n_matched: 0,
+            last_search_timing: None,
+        }
+    }
+}
+
+/// Line number, contents and matched positions of the lines of the file matching the query,
+/// read one line at a time. None if the file can't be read, is not valid UTF-8 (binaries etc.)
+/// or the search was cancelled while reading it.
+fn grep_file(
+    path: &str,
+    query: &TextQuery,
+    handle: &SearchHandle,
+) -> Option<Vec<(usize, String, Vec<usize>)>> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut matching_lines = Vec::new();
+    let mut buffer = Vec::new();
+    for line_id in 0.. {
+        if line_id % CANCEL_CHECK_LINES == 0 && handle.is_cancelled() {
+            return None;
+        }
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer).ok()? == 0 {
+            break;
+        }
+        let line = std::str::from_utf8(&buffer).ok()?;
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(found) = query.matches(line, MatchMode::Substring) {
+            matching_lines.push((line_id + 1, line.to_string(), found.positions));
+        }
+    }
+    Some(matching_lines)
+}
+
+impl TelescopeSearch for LiveGrepSearch {
+    fn search(&mut self, query: String, handle: &SearchHandle) {
+        let start = Instant::now();
+        self.results.clear();
+        self.n_matched = 0;
+        //The popup only starts searches for queries which parse
+        let parsed = TextQuery::parse(&query, self.options.mode).unwrap_or_default();
+        self.query = query;
+        if parsed.is_empty() {
+            self.last_search_timing = None;
+            return;
+        }
+        let mut paths = walk_files(&self.absolute_directory, &self.options)
+            .map(|file| file.path)
+            .filter(|path| {
+                fs::metadata(path).is_ok_and(|metadata| {
+                    metadata.is_file()
+                        && special_file_kind(&metadata).is_none()
+                        && metadata.len() <= MAX_GREP_FILE_SIZE
+                })
+            })
+            .collect::<Vec<String>>();
+        paths.sort();
+
+        //Lines past the result limit are still counted, to tell how many were left out
+        for path in paths {
+            if handle.is_cancelled() {
+                return;
+            }
+            if handle.should_publish() {
+                self.last_search_timing = Some(start.elapsed());
+                handle.publish(self);
+            }
+            let matching_lines = match grep_file(&path, &parsed, handle) {
+                Some(matching_lines) => matching_lines,
+                None => continue,
+            };
+            let displayed_path = display_path(
+                &path,
+                &self.absolute_directory,
+                self.options.path_display,
+                &[],
+            )
+            .text;
+            for (line_number, line, positions) in matching_lines {
+                self.n_matched += 1;
+                if self.results.len() < self.options.max_results {
+                    let column = positions.first().map_or(1, |p| p + 1);
+                    let mut result =
+                        LiveGrepResult::new(path.clone(), line_number, column, line, positions);
+                    result.displayed_path = displayed_path.clone();
+                    self.results.push(result);
+                }
+            }
+        }
+        self.last_search_timing = Some(start.elapsed());
+    }
+
+    fn confirm_result(&mut self, id: usize) -> Option<Action> {
+        let result = &self.results[id];
+        let path = Path::new(&result.path).to_path_buf();
+        Some(Action::AppAct(AppAction::ShowInFolder(path)))
+    }
+
+    fn get_result_rows(&self) -> Vec<ResultRow> {
+        self.results
+            .iter()
+            .map(|r| {
+                //The line contents follow the location prefix, without their leading whitespace
+                let offset = r.location().chars().count();
+                let indent = r.contents.chars().count() - r.contents.trim_start().chars().count();
+                let length = r.contents.trim().chars().count();
+                let positions = r
+                    .matched_positions
+                    .iter()
+                    .filter(|p| (indent..indent + length).contains(p))
+                    .map(|p| p - indent + offset)
+                    .collect();
+                HighlightedText::new(r.display(), positions)
+            })
+            .map(ResultRow::from)
+            .collect::<Vec<ResultRow>>()
+    }
+
+    fn display(&self) -> String {
+        let elapsed = match &self.last_search_timing {
+            Some(d) => (d.as_millis() as f64 / 1000.0).to_string(),
+            None => "".to_string(),
+        };
+        format!("Live grep [{}] - {}", self.options.mode.label(), elapsed)
+    }
+
+    fn check_query(&self, query: &str) -> Result<(), QueryError> {
+        TextQuery::parse(query, self.options.mode).map(|_| ())
+    }
+
+    fn cycle_query_mode(&mut self) {
+        self.options.mode = self.options.mode.next();
+    }
+
+    fn toggle_filter(&mut self, filter: SearchFilter) {
+        match filter {
+            SearchFilter::Hidden => self.options.hidden = !self.options.hidden,
+            SearchFilter::Ignored => self.options.ignored = !self.options.ignored,
+        }
+    }
+
+    fn preview_result(
+        &self,
+        some_id: Option<usize>,
+        scroll: u16,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Result<u16> {
+        let preview_block = Block::default().borders(Borders::ALL).title("Preview");
+        match some_id {
+            Some(id) => return self.results[id].preview(frame, area, preview_block, scroll),
+            None => {
+                frame.render_widget(Paragraph::default().block(preview_block), area);
+            }
+        };
+        Ok(0)
+    }
+
+    fn n_results(&self) -> usize {
+        self.results.len()
+    }
+
+    fn n_matched(&self) -> usize {
+        self.n_matched
+    }
+
+    fn get_path(&self, id: usize) -> Option<PathBuf> {
+        self.results.get(id).map(|r| PathBuf::from(&r.path))
+    }
+
+    fn preview_id(&self, id: usize) -> Option<String> {
+        self.results
+            .get(id)
+            .map(|r| format!("{}:{}", r.path, r.line))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LiveGrepResult {
+    path: String,
+    //Path shown in the results, as configured by the path display
+    displayed_path: String,
+    line: usize,
+    column: usize,
+    contents: String,
+    //Character positions within the line matched by the query
+    matched_positions: Vec<usize>,
+}
+
+impl LiveGrepResult {
+    pub fn new(
+        path: String,
+        line: usize,
+        column: usize,
+        contents: String,
+        matched_positions: Vec<usize>,
+    ) -> Self {
+        Self {
+            displayed_path: path.clone(),
+            path,
+            line,
+            column,
+            contents,
+            matched_positions,
+        }
+    }
+
+    fn location(&self) -> String {
+        format!("{}:{}:{}: ", self.displayed_path, self.line, self.column)
+    }
+}
+
+impl TelescopeResult for LiveGrepResult {
+    fn display(&self) -> String {
+        format!("{}{}", self.location(), self.contents.trim())
+    }
+
+    fn preview(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        preview_block: Block,
+        scroll: u16,
+    ) -> Result<u16> {
+        //Render the file around the matching line, keeping the match in the middle of the area
+        //until the preview is scrolled. The file preview reads only the part of the file up to the
+        //bottom of the area, so the lines before the area are skipped.
+        let centred = self.line.saturating_sub(area.height as usize / 2);
+        let mut lines = preview_lines(
+            Path::new(&self.path),
+            centred + scroll as usize + area.height as usize,
+        );
+        let scroll = clamp_scroll(scroll, lines.len().saturating_sub(centred), area);
+        let first_line = centred + scroll as usize;
+        //a file too large to be loaded up to the area only shows the note about it at the end
+        let start = first_line.min(lines.len().saturating_sub(1));
+        let lines = lines
+            .split_off(start)
+            .into_iter()
+            .enumerate()
+            .map(|(line_id, line)| match start + line_id + 1 == self.line {
+                true => line.patch_style(Style::default().add_modifier(Modifier::REVERSED)),
+                false => line,
+            })
+            .collect::<Vec<Line>>();
+        let paragraph = Paragraph::new(Text::from(lines)).block(preview_block);
+
+        frame.render_widget(paragraph, area);
+        Ok(scroll)
+    }
+
+    fn from<S>(s: S) -> Self
+    where
+        S: ToString + Display,
+    {
+        //Accepts the "path:line:column" format, falling back to the beginning of the file
+        let s = s.to_string();
+        let mut parts = s.rsplitn(3, ':');
+        let (column, line, path) = (parts.next(), parts.next(), parts.next());
+        match (
+            path,
+            line.and_then(|l| l.parse().ok()),
+            column.and_then(|c| c.parse().ok()),
+        ) {
+            (Some(path), Some(line), Some(column)) => {
+                Self::new(path.to_string(), line, column, String::new(), Vec::new())
+            }
+            _ => Self::new(s, 1, 1, String::new(), Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extended_query::QueryMode;
+    use blaze_explorer_lib::app::App;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn test_search_follows_the_walk_options() {
+        let mut ctx = App::new().unwrap().get_app_context();
+        ctx.current_directory = ctx.current_directory.join("tests/ignore_rules");
+        let mut search = LiveGrepSearch::new(ctx, SearchConfig::default());
+        let matched_files = |search: &mut LiveGrepSearch| {
+            search.search("e".to_string(), &SearchHandle::detached());
+            search
+                .results
+                .iter()
+                .map(|r| r.displayed_path.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(matched_files(&mut search), vec![".hidden.txt", "kept.txt"]);
+
+        search.toggle_filter(SearchFilter::Ignored);
+        assert_eq!(
+            matched_files(&mut search),
+            vec![".hidden.txt", "kept.txt", "skipped.log"]
+        );
+
+        search.toggle_filter(SearchFilter::Hidden);
+        assert_eq!(matched_files(&mut search), vec!["kept.txt", "skipped.log"]);
+    }
+
+    #[test]
+    fn test_preview_marks_the_matching_line() {
+        let result =
+            <LiveGrepResult as TelescopeResult>::from("tests/folder_1/example_text.txt:2:5");
+        let mut terminal = Terminal::new(TestBackend::new(60, 6)).unwrap();
+        terminal
+            .draw(|frame| {
+                let block = Block::default().borders(Borders::ALL);
+                result.preview(frame, frame.size(), block, 0).unwrap();
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| {
+            (1..59)
+                .map(|x| buffer.get(x, y).symbol())
+                .collect::<String>()
+        };
+        assert!(row(1).starts_with("Example text file"));
+        assert!(row(2).starts_with("The zebra"));
+        assert!(buffer.get(1, 2).modifier.contains(Modifier::REVERSED));
+        assert!(!buffer.get(1, 1).modifier.contains(Modifier::REVERSED));
+    }
+
+    #[test]
+    fn test_grep_file_reads_line_by_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("crlf.txt");
+        fs::write(&text, "first\r\nsecond zebra\r\nlast").unwrap();
+        let binary = dir.path().join("binary.bin");
+        fs::write(&binary, b"zebra\n\xff\xfe").unwrap();
+        let query = TextQuery::parse("zebra", QueryMode::default()).unwrap();
+        let handle = SearchHandle::detached();
+
+        let lines = grep_file(&text.display().to_string(), &query, &handle).unwrap();
+        assert_eq!(
+            lines,
+            vec![(2, "second zebra".to_string(), vec![7, 8, 9, 10, 11])]
+        );
+        assert_eq!(
+            grep_file(&binary.display().to_string(), &query, &handle),
+            None
+        );
+    }
+}
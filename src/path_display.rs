@@ -0,0 +1,227 @@
+//How the paths of the results are displayed, relative to the search root by default:
+//  relative        src/telescope/backend.rs
+//  absolute        /home/me/project/src/telescope/backend.rs
+//  basename_first  backend.rs  src/telescope
+//  shorten         s/t/backend.rs
+//  truncate        src/…/backend.rs, leaving out the middle directories to fit the results column
+
+use std::ops::Range;
+
+use serde::Deserialize;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::telescope_backend::HighlightedText;
+
+const ELLIPSIS: char = '…';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathDisplay {
+    #[default]
+    Relative,
+    Absolute,
+    BasenameFirst,
+    Shorten,
+    Truncate,
+}
+
+/// Text put together from characters of the original text and inserted ones, keeping track of
+/// where each character came from so that the highlighted characters can follow them
+#[derive(Debug, Default)]
+struct DisplayBuilder {
+    text: String,
+    sources: Vec<Option<usize>>,
+}
+
+impl DisplayBuilder {
+    fn push_chars(&mut self, chars: &[char], range: Range<usize>) {
+        for id in range {
+            self.text.push(chars[id]);
+            self.sources.push(Some(id));
+        }
+    }
+
+    fn push_inserted(&mut self, ch: char) {
+        self.text.push(ch);
+        self.sources.push(None);
+    }
+
+    /// Highlight the characters coming from the given positions of the original text
+    fn highlight(self, positions: &[usize]) -> HighlightedText {
+        let displayed = self
+            .sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.is_some_and(|source| positions.contains(&source)))
+            .map(|(id, _)| id)
+            .collect();
+        HighlightedText::new(self.text, displayed)
+    }
+}
+
+/// Strip the search root from the path, leaving the part relative to it
+pub fn relative_path<'a>(path: &'a str, root: &str) -> &'a str {
+    match path.strip_prefix(root) {
+        Some(relative) => relative.trim_start_matches(['/', '\\']),
+        None => path,
+    }
+}
+
+fn is_separator(ch: char) -> bool {
+    ch == '/' || ch == '\\'
+}
+
+/// Display the path below the search root as configured, highlighting the characters at the
+/// given positions of the full path
+pub fn display_path(
+    path: &str,
+    root: &str,
+    mode: PathDisplay,
+    positions: &[usize],
+) -> HighlightedText {
+    let chars = path.chars().collect::<Vec<char>>();
+    let start = chars.len() - relative_path(path, root).chars().count();
+    let separators = (start..chars.len())
+        .filter(|id| is_separator(chars[*id]))
+        .collect::<Vec<usize>>();
+    let name_start = separators.last().map_or(start, |separator| separator + 1);
+    let mut display = DisplayBuilder::default();
+    match mode {
+        PathDisplay::Absolute => display.push_chars(&chars, 0..chars.len()),
+        //truncating depends on the width of the results column, see `truncate_path`
+        PathDisplay::Relative | PathDisplay::Truncate => {
+            display.push_chars(&chars, start..chars.len())
+        }
+        PathDisplay::BasenameFirst => {
+            display.push_chars(&chars, name_start..chars.len());
+            if name_start > start {
+                display.push_inserted(' ');
+                display.push_inserted(' ');
+                display.push_chars(&chars, start..name_start - 1);
+            }
+        }
+        PathDisplay::Shorten => {
+            //keep the first character of each directory, or the first two of hidden ones
+            let mut segment_start = start;
+            for separator in separators {
+                let kept = match chars[segment_start] {
+                    '.' => 2,
+                    _ => 1,
+                };
+                display.push_chars(&chars, segment_start..(segment_start + kept).min(separator));
+                display.push_chars(&chars, separator..separator + 1);
+                segment_start = separator + 1;
+            }
+            display.push_chars(&chars, name_start..chars.len());
+        }
+    }
+    display.highlight(positions)
+}
+
+/// Leave directories out of the middle of the path until it fits the width in terminal cells,
+/// e.g. src/…/dir/file.rs. If the file name alone doesn't fit, its start is cut off as well.
+pub fn truncate_path(path: &HighlightedText, width: usize) -> HighlightedText {
+    if path.text.width() <= width {
+        return path.clone();
+    }
+    let chars = path.text.chars().collect::<Vec<char>>();
+    let cells = |range: Range<usize>| {
+        chars[range]
+            .iter()
+            .map(|ch| ch.width().unwrap_or(0))
+            .sum::<usize>()
+    };
+    let ellipsis_width = ELLIPSIS.width().unwrap_or(1);
+    let separators = (0..chars.len())
+        .filter(|id| is_separator(chars[*id]))
+        .collect::<Vec<usize>>();
+    let mut display = DisplayBuilder::default();
+
+    //keep the first directory and as many of the last ones as fit
+    if let Some(first) = separators.first() {
+        let head = 0..first + 1;
+        for separator in separators.iter().skip(1) {
+            let tail = *separator..chars.len();
+            if cells(head.clone()) + ellipsis_width + cells(tail.clone()) <= width {
+                display.push_chars(&chars, head);
+                display.push_inserted(ELLIPSIS);
+                display.push_chars(&chars, tail);
+                return display.highlight(&path.positions);
+            }
+        }
+    }
+
+    //otherwise keep the end of the path
+    let mut tail_start = chars.len();
+    let mut tail_width = ellipsis_width;
+    while tail_start > 0 && tail_width + cells(tail_start - 1..tail_start) <= width {
+        tail_start -= 1;
+        tail_width += cells(tail_start..tail_start + 1);
+    }
+    if tail_width <= width {
+        display.push_inserted(ELLIPSIS);
+        display.push_chars(&chars, tail_start..chars.len());
+    }
+    display.highlight(&path.positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "/home/me/project";
+    const PATH: &str = "/home/me/project/src/telescope/backend.rs";
+
+    fn highlighted(text: &HighlightedText) -> String {
+        text.positions
+            .iter()
+            .filter_map(|id| text.text.chars().nth(*id))
+            .collect()
+    }
+
+    #[test]
+    fn test_display_modes() {
+        //"src" and "backend" are matched
+        let positions = (17..20).chain(31..38).collect::<Vec<usize>>();
+        let display = |mode| display_path(PATH, ROOT, mode, &positions);
+
+        assert_eq!(
+            display(PathDisplay::Relative).text,
+            "src/telescope/backend.rs"
+        );
+        assert_eq!(display(PathDisplay::Absolute).text, PATH);
+        assert_eq!(
+            display(PathDisplay::BasenameFirst).text,
+            "backend.rs  src/telescope"
+        );
+        assert_eq!(display(PathDisplay::Shorten).text, "s/t/backend.rs");
+        assert_eq!(
+            display_path("/root/.config/a.rs", "/root", PathDisplay::Shorten, &[]).text,
+            ".c/a.rs"
+        );
+        for mode in [PathDisplay::Relative, PathDisplay::Absolute] {
+            assert_eq!(highlighted(&display(mode)), "srcbackend");
+        }
+        assert_eq!(
+            highlighted(&display(PathDisplay::BasenameFirst)),
+            "backendsrc"
+        );
+        assert_eq!(highlighted(&display(PathDisplay::Shorten)), "sbackend");
+    }
+
+    #[test]
+    fn test_truncate_path() {
+        let path = HighlightedText::new("src/a/bb/ccc/file.rs".to_string(), vec![0, 14]);
+        assert_eq!(truncate_path(&path, 20), path);
+        let truncated = truncate_path(&path, 17);
+        assert_eq!(truncated.text, "src/…/ccc/file.rs");
+        assert_eq!(highlighted(&truncated), "si");
+        assert_eq!(truncate_path(&path, 8).text, "…file.rs");
+        assert_eq!(truncate_path(&path, 0).text, "");
+
+        //wide characters take up two cells each
+        let path = HighlightedText::new("src/日本/語.rs".to_string(), Vec::new());
+        assert_eq!(truncate_path(&path, 11).text, "src/…/語.rs");
+        assert_eq!(truncate_path(&path, 6).text, "…語.rs");
+    }
+}
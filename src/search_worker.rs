@@ -0,0 +1,259 @@
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::telescope_backend::TelescopeSearch;
+
+//Minimum time between two partial results sent by a running search
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// State of the search sent from the worker thread to the UI
+#[derive(Debug)]
+pub enum SearchUpdate {
+    Partial(Box<dyn TelescopeSearch>),
+    Finished(Box<dyn TelescopeSearch>),
+}
+
+/// Handle passed to a search running on the worker thread. It tells the search whether a newer
+/// query made it stale and lets it stream partial results to the UI.
+#[derive(Debug)]
+pub struct SearchHandle {
+    cancelled: Arc<AtomicBool>,
+    sender: Option<Sender<SearchUpdate>>,
+    last_publish: Cell<Instant>,
+}
+
+impl SearchHandle {
+    fn new(cancelled: Arc<AtomicBool>, sender: Sender<SearchUpdate>) -> Self {
+        Self {
+            cancelled,
+            sender: Some(sender),
+            last_publish: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Handle for a search run directly on the calling thread, with nobody listening for partial
+    /// results
+    pub fn detached() -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            sender: None,
+            last_publish: Cell::new(Instant::now()),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Whether enough time has passed since the last partial result to send a new one
+    pub fn should_publish(&self) -> bool {
+        self.sender.is_some() && self.last_publish.get().elapsed() >= PUBLISH_INTERVAL
+    }
+
+    /// Send the current state of the search to the UI as a partial result
+    pub fn publish(&self, search: &dyn TelescopeSearch) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(SearchUpdate::Partial(search.clone_box()));
+            self.last_publish.set(Instant::now());
+        }
+    }
+
+    fn finish(&self, search: Box<dyn TelescopeSearch>) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(SearchUpdate::Finished(search));
+        }
+    }
+}
+
+/// Runs searches on a background thread, keeping track of the most recent one only
+#[derive(Debug, Clone, Default)]
+pub struct SearchWorker {
+    receiver: Option<Arc<Mutex<Receiver<SearchUpdate>>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl SearchWorker {
+    /// Run the query on a copy of the search in the background, cancelling the previous query
+    pub fn start(&mut self, search: &dyn TelescopeSearch, query: String) {
+        self.cancel();
+        let (sender, receiver) = channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut search = search.clone_box();
+        let handle = SearchHandle::new(cancelled.clone(), sender);
+        thread::spawn(move || {
+            search.search(query, &handle);
+            if !handle.is_cancelled() {
+                handle.finish(search);
+            }
+        });
+        self.receiver = Some(Arc::new(Mutex::new(receiver)));
+        self.cancelled = cancelled;
+    }
+
+    pub fn cancel(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.receiver = None;
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Return the most recent state of the running search without blocking
+    pub fn poll(&mut self) -> Option<Box<dyn TelescopeSearch>> {
+        let receiver = self.receiver.clone()?;
+        let receiver = receiver.lock().ok()?;
+        let mut latest = None;
+        loop {
+            match receiver.try_recv() {
+                Ok(SearchUpdate::Partial(search)) => latest = Some(search),
+                Ok(SearchUpdate::Finished(search)) => {
+                    self.receiver = None;
+                    return Some(search);
+                }
+                Err(TryRecvError::Empty) => return latest,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    return latest;
+                }
+            }
+        }
+    }
+
+    /// Block until the running search finishes and return its final state
+    pub fn wait(&mut self) -> Option<Box<dyn TelescopeSearch>> {
+        let receiver = self.receiver.take()?;
+        let receiver = receiver.lock().ok()?;
+        let mut latest = None;
+        while let Ok(update) = receiver.recv() {
+            match update {
+                SearchUpdate::Partial(search) => latest = Some(search),
+                SearchUpdate::Finished(search) => return Some(search),
+            }
+        }
+        latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telescope_backend::{HighlightedText, ResultRow};
+    use blaze_explorer_lib::action::Action;
+    use color_eyre::eyre::Result;
+    use ratatui::{layout::Rect, Frame};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Search publishing its first result right away and finding the second one once released.
+    /// Flags shared by all of its copies tell the test what the searches on the worker saw.
+    #[derive(Debug, Clone, Default)]
+    struct StepSearch {
+        query: String,
+        results: Vec<String>,
+        release: Arc<AtomicBool>,
+        saw_cancel: Arc<AtomicBool>,
+    }
+
+    impl PartialEq for StepSearch {
+        fn eq(&self, other: &Self) -> bool {
+            self.query == other.query && self.results == other.results
+        }
+    }
+
+    impl TelescopeSearch for StepSearch {
+        fn search(&mut self, query: String, handle: &SearchHandle) {
+            self.results = vec![format!("{} 1", query)];
+            self.query = query;
+            handle.publish(self);
+            while !self.release.load(Ordering::SeqCst) {
+                if handle.is_cancelled() {
+                    self.saw_cancel.store(true, Ordering::SeqCst);
+                    return;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            self.results.push(format!("{} 2", self.query));
+        }
+
+        fn get_result_rows(&self) -> Vec<ResultRow> {
+            self.results
+                .iter()
+                .map(|r| ResultRow::from(HighlightedText::new(r.clone(), Vec::new())))
+                .collect()
+        }
+
+        fn confirm_result(&mut self, _id: usize) -> Option<Action> {
+            None
+        }
+
+        fn preview_result(
+            &self,
+            _id: Option<usize>,
+            _scroll: u16,
+            _frame: &mut Frame,
+            _area: Rect,
+        ) -> Result<u16> {
+            Ok(0)
+        }
+
+        fn display(&self) -> String {
+            self.query.clone()
+        }
+
+        fn n_results(&self) -> usize {
+            self.results.len()
+        }
+    }
+
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < TIMEOUT, "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_newer_search_cancels_stale_one() {
+        let search = StepSearch::default();
+        let mut worker = SearchWorker::default();
+        worker.start(&search, "stale".to_string());
+        worker.start(&search, "fresh".to_string());
+        //the stale search can only stop by noticing it was cancelled
+        wait_until(|| search.saw_cancel.load(Ordering::SeqCst));
+        search.release.store(true, Ordering::SeqCst);
+
+        let finished = worker.wait().unwrap();
+        assert_eq!(finished.display(), "fresh");
+        assert_eq!(finished.n_results(), 2);
+        assert!(!worker.is_searching());
+    }
+
+    #[test]
+    fn test_partial_results_are_published() {
+        let search = StepSearch::default();
+        let mut worker = SearchWorker::default();
+        worker.start(&search, "query".to_string());
+        let mut partial = None;
+        wait_until(|| {
+            partial = worker.poll();
+            partial.is_some()
+        });
+        let partial = partial.unwrap();
+        assert_eq!(partial.n_results(), 1);
+        assert!(worker.is_searching());
+
+        search.release.store(true, Ordering::SeqCst);
+        let finished = worker.wait().unwrap();
+        assert_eq!(finished.n_results(), 2);
+    }
+}
@@ -1,70 +1,271 @@
 use std::{
     fmt::Display,
-    fs::read_to_string,
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     time::{Duration, Instant},
 };
 
 use color_eyre::eyre::Result;
 use ratatui::{
     layout::Rect,
-    text::{Line, Text},
+    text::Text,
     widgets::{Block, Borders, Paragraph},
     Frame,
 };
-use rust_search::SearchBuilder;
 
 use blaze_explorer_lib::{
     action::{Action, AppAction},
     app_context::AppContext,
 };
 
-use crate::telescope_backend::{TelescopeResult, TelescopeSearch};
+use crate::{
+    config::SearchConfig,
+    extended_query::{MatchMode, QueryError},
+    file_columns::SortMode,
+    file_preview::preview_lines,
+    file_query::{FileInfo, FileQuery},
+    file_walk::{walk_files, CandidateFile},
+    path_display::{display_path, relative_path, truncate_path, PathDisplay},
+    search_worker::SearchHandle,
+    telescope_backend::{
+        clamp_scroll, HighlightedText, ResultRow, SearchFilter, TelescopeResult, TelescopeSearch,
+    },
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SearchFileshereSearch {
     absolute_directory: String,
+    options: SearchConfig,
+    candidates: CandidateCache,
+    //Query of the last completed search and the ids of all candidates it matched
+    last_query: FileQuery,
+    matched: Arc<Vec<usize>>,
     results: Vec<SearchFilesHereResult>,
+    //Number of files matched by the last search, of which only the best ones are kept
+    n_matched: usize,
     last_search_timing: Option<Duration>,
 }
 
 impl SearchFileshereSearch {
-    pub fn new(ctx: AppContext) -> Self {
+    pub fn new(ctx: AppContext, options: SearchConfig) -> Self {
         Self {
             absolute_directory: ctx.current_directory.display().to_string(),
+            options,
+            candidates: CandidateCache::default(),
+            last_query: FileQuery::default(),
+            matched: Arc::new(Vec::new()),
             results: Vec::new(),
+            n_matched: 0,
             last_search_timing: None,
         }
     }
+
+    /// Check the metadata of the file against the predicates of the query, and score its path
+    /// against the text, matching only the part below the search root
+    fn score_file(&self, file: &CandidateFile, query: &FileQuery) -> Option<SearchFilesHereResult> {
+        let path = &file.path;
+        if !query.accepts(Path::new(path), file.info.as_ref()) {
+            return None;
+        }
+        let relative = relative_path(path, &self.absolute_directory);
+        let offset = path.chars().count() - relative.chars().count();
+        let fuzzy = query.text.matches(relative, MatchMode::Fuzzy)?;
+        let positions = fuzzy.positions.iter().map(|p| p + offset).collect();
+        Some(SearchFilesHereResult::new(
+            path.to_string(),
+            fuzzy.score,
+            positions,
+            file.info,
+        ))
+    }
+
+    /// Walk the search root, matching the query on the fly. The walk is completed even if the
+    /// search gets cancelled, so that the candidates can be cached for the following queries,
+    /// unless a refresh discarded the cache in the meantime
+    fn walk(
+        &mut self,
+        query: &FileQuery,
+        handle: &SearchHandle,
+        start: Instant,
+    ) -> Option<(Vec<CandidateFile>, Vec<usize>, Vec<SearchFilesHereResult>)> {
+        let mut files = Vec::new();
+        let mut matched = Vec::new();
+        let mut results = Vec::new();
+        for file in walk_files(&self.absolute_directory, &self.options) {
+            if handle.is_cancelled() && self.candidates.is_discarded() {
+                return None;
+            }
+            if !handle.is_cancelled() {
+                if let Some(result) = self.score_file(&file, query) {
+                    matched.push(files.len());
+                    results.push(result);
+                }
+                if handle.should_publish() {
+                    rank_results(&mut results, self.options.max_results, self.options.sort);
+                    self.results = results.clone();
+                    self.n_matched = matched.len();
+                    self.last_search_timing = Some(start.elapsed());
+                    handle.publish(self);
+                }
+            }
+            files.push(file);
+        }
+        Some((files, matched, results))
+    }
+
+    /// Match the query against the cached candidates. When the query narrows down the previous
+    /// one, only the candidates matched previously need to be checked.
+    fn filter_candidates(
+        &mut self,
+        files: &[CandidateFile],
+        query: &FileQuery,
+        handle: &SearchHandle,
+        start: Instant,
+    ) -> Option<(Vec<usize>, Vec<SearchFilesHereResult>)> {
+        let ids: Vec<usize> = match query.narrows(&self.last_query) {
+            true => self.matched.to_vec(),
+            false => (0..files.len()).collect(),
+        };
+        let mut matched = Vec::new();
+        let mut results = Vec::new();
+        for id in ids {
+            if handle.is_cancelled() {
+                return None;
+            }
+            if let Some(result) = self.score_file(&files[id], query) {
+                matched.push(id);
+                results.push(result);
+            }
+            if handle.should_publish() {
+                rank_results(&mut results, self.options.max_results, self.options.sort);
+                self.results = results.clone();
+                self.n_matched = matched.len();
+                self.last_search_timing = Some(start.elapsed());
+                handle.publish(self);
+            }
+        }
+        Some((matched, results))
+    }
 }
 impl TelescopeSearch for SearchFileshereSearch {
-    fn search(&mut self, query: String) {
+    fn search(&mut self, query: String, handle: &SearchHandle) {
         let start = Instant::now();
-        self.results = SearchBuilder::default()
-            .location(self.absolute_directory.clone())
-            .search_input(query)
-            .limit(1000) // results to return
-            // .strict()
-            .ignore_case()
-            .hidden()
-            .build()
-            .map(SearchFilesHereResult::new)
-            .collect::<Vec<SearchFilesHereResult>>();
-        self.results.sort_by_key(|r| r.path.clone());
+        //The popup only starts searches for queries which parse
+        let parsed = FileQuery::parse(&query, self.options.mode).unwrap_or_default();
+        //Holding the cache while walking makes newer queries wait for the walk instead of
+        //starting their own
+        let cache = self.candidates.clone();
+        let mut cached = cache.lock();
+        let cached_files = cached
+            .as_ref()
+            .filter(|candidates| candidates.root == self.absolute_directory)
+            .map(|candidates| candidates.files.clone());
+        let (matched, mut results) = match cached_files {
+            Some(files) => {
+                drop(cached);
+                match self.filter_candidates(&files, &parsed, handle, start) {
+                    Some(filtered) => filtered,
+                    None => return,
+                }
+            }
+            None => {
+                let (files, matched, results) = match self.walk(&parsed, handle, start) {
+                    Some(walked) => walked,
+                    None => return,
+                };
+                *cached = Some(Candidates {
+                    root: self.absolute_directory.clone(),
+                    files: Arc::new(files),
+                });
+                if handle.is_cancelled() {
+                    return;
+                }
+                (matched, results)
+            }
+        };
+        rank_results(&mut results, self.options.max_results, self.options.sort);
+        self.results = results;
+        self.n_matched = matched.len();
+        self.matched = Arc::new(matched);
+        self.last_query = parsed;
         self.last_search_timing = Some(start.elapsed());
     }
 
+    fn check_query(&self, query: &str) -> Result<(), QueryError> {
+        FileQuery::parse(query, self.options.mode).map(|_| ())
+    }
+
+    fn cycle_query_mode(&mut self) {
+        self.options.mode = self.options.mode.next();
+    }
+
+    fn refresh(&mut self) {
+        //A walk still running for the old cache stops, without the UI waiting for it
+        self.candidates.discard();
+        self.candidates = CandidateCache::default();
+        self.last_query = FileQuery::default();
+        self.matched = Arc::new(Vec::new());
+    }
+
+    fn cycle_sort(&mut self) {
+        self.options.sort = self.options.sort.next(&self.options.columns);
+    }
+
+    fn toggle_filter(&mut self, filter: SearchFilter) {
+        match filter {
+            SearchFilter::Hidden => self.options.hidden = !self.options.hidden,
+            SearchFilter::Ignored => self.options.ignored = !self.options.ignored,
+        }
+    }
+
     fn confirm_result(&mut self, id: usize) -> Option<Action> {
         let result = &self.results[id];
         let path = Path::new(&result.path).to_path_buf();
         Some(Action::AppAct(AppAction::ShowInFolder(path)))
     }
 
-    fn get_results_list(&self) -> Vec<String> {
+    fn get_result_rows(&self) -> Vec<ResultRow> {
         self.results
             .iter()
-            .map(|r| r.display())
-            .collect::<Vec<String>>()
+            .map(|r| ResultRow {
+                text: display_path(
+                    &r.path,
+                    &self.absolute_directory,
+                    self.options.path_display,
+                    &r.matched_positions,
+                ),
+                columns: self
+                    .options
+                    .columns
+                    .iter()
+                    .map(|column| match &r.info {
+                        Some(info) => column.format(info),
+                        None => "?".to_string(),
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<ResultRow>>()
+    }
+
+    fn fit_result(&self, text: HighlightedText, width: usize) -> HighlightedText {
+        match self.options.path_display {
+            PathDisplay::Truncate => truncate_path(&text, width),
+            _ => text,
+        }
+    }
+
+    fn column_names(&self) -> Vec<String> {
+        match self.options.columns.is_empty() {
+            true => Vec::new(),
+            false => ["Path"]
+                .into_iter()
+                .chain(self.options.columns.iter().map(|column| column.name()))
+                .map(|name| name.to_string())
+                .collect(),
+        }
     }
 
     fn display(&self) -> String {
@@ -72,33 +273,121 @@ impl TelescopeSearch for SearchFileshereSearch {
             Some(d) => (d.as_millis() as f64 / 1000.0).to_string(),
             None => "".to_string(),
         };
-        format!("Search here - {}", elapsed)
+        let state = |shown: bool| match shown {
+            true => "on",
+            false => "off",
+        };
+        format!(
+            "Search here [{}, hidden: {}, ignored: {}, sorted by {}] - {}",
+            self.options.mode.label(),
+            state(self.options.hidden),
+            state(self.options.ignored),
+            self.options.sort.label(),
+            elapsed
+        )
     }
 
-    fn preview_result(&self, some_id: Option<usize>, frame: &mut Frame, area: Rect) -> Result<()> {
+    fn preview_result(
+        &self,
+        some_id: Option<usize>,
+        scroll: u16,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Result<u16> {
         let preview_block = Block::default().borders(Borders::ALL).title("Preview");
         match some_id {
-            Some(id) => return self.results[id].preview(frame, area, preview_block),
+            Some(id) => return self.results[id].preview(frame, area, preview_block, scroll),
             None => {
                 frame.render_widget(Paragraph::default().block(preview_block), area);
             }
         };
-        Ok(())
+        Ok(0)
     }
 
     fn n_results(&self) -> usize {
         self.results.len()
     }
+
+    fn n_matched(&self) -> usize {
+        self.n_matched
+    }
+
+    fn get_path(&self, id: usize) -> Option<PathBuf> {
+        self.results.get(id).map(|r| PathBuf::from(&r.path))
+    }
+}
+
+/// Files found under the search root during the first search of the popup session
+#[derive(Debug)]
+struct Candidates {
+    root: String,
+    files: Arc<Vec<CandidateFile>>,
+}
+
+/// Candidates shared by every copy of the search made for the background worker, so that the
+/// filesystem is only walked once per popup session. Only the worker locks it - refreshing
+/// replaces the cache with a new one instead of clearing it, as a walk may be holding the lock.
+#[derive(Debug, Clone, Default)]
+struct CandidateCache {
+    candidates: Arc<Mutex<Option<Candidates>>>,
+    discarded: Arc<AtomicBool>,
+}
+
+impl CandidateCache {
+    fn lock(&self) -> MutexGuard<'_, Option<Candidates>> {
+        self.candidates
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Mark the cache as replaced, so that nothing gets walked for it anymore
+    fn discard(&self) {
+        self.discarded.store(true, Ordering::Relaxed);
+    }
+
+    fn is_discarded(&self) -> bool {
+        self.discarded.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CandidateCache {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.candidates, &other.candidates)
+    }
+}
+
+/// Order the results by the sort mode, then from the best match, and keep only the first ones
+fn rank_results(results: &mut Vec<SearchFilesHereResult>, max_results: usize, sort: SortMode) {
+    results.sort_by(|a, b| {
+        sort.compare((&a.path, a.info.as_ref()), (&b.path, b.info.as_ref()))
+            .then(b.score.cmp(&a.score))
+            .then(a.path.len().cmp(&b.path.len()))
+            .then(a.path.cmp(&b.path))
+    });
+    results.truncate(max_results);
 }
 
 #[derive(Debug, Clone, PartialEq)]
 struct SearchFilesHereResult {
     path: String,
+    score: i64,
+    matched_positions: Vec<usize>,
+    info: Option<FileInfo>,
 }
 
 impl SearchFilesHereResult {
-    pub fn new(path: String) -> Self {
-        Self { path }
+    pub fn new(
+        path: String,
+        score: i64,
+        matched_positions: Vec<usize>,
+        info: Option<FileInfo>,
+    ) -> Self {
+        Self {
+            path,
+            score,
+            matched_positions,
+            info,
+        }
     }
 }
 
@@ -107,22 +396,67 @@ impl TelescopeResult for SearchFilesHereResult {
         self.path.clone()
     }
 
-    fn preview(&self, frame: &mut Frame, area: Rect, preview_block: Block) -> Result<()> {
-        //Render a preview of the contents of the file
-        let contents = read_to_string(&self.path).unwrap_or("Could not read the file".to_string());
-        let lines = contents.lines().map(Line::from).collect::<Vec<Line>>();
-        let paragraph = Paragraph::new(Text::from(lines)).block(preview_block);
+    fn preview(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        preview_block: Block,
+        scroll: u16,
+    ) -> Result<u16> {
+        //Render a preview of the contents of the file, loading the lines scrolled past as well
+        let lines = preview_lines(
+            Path::new(&self.path),
+            area.height as usize + scroll as usize,
+        );
+        let scroll = clamp_scroll(scroll, lines.len(), area);
+        let paragraph = Paragraph::new(Text::from(lines))
+            .block(preview_block)
+            .scroll((scroll, 0));
 
         frame.render_widget(paragraph, area);
-        Ok(())
+        Ok(scroll)
     }
 
     fn from<S>(s: S) -> Self
     where
         S: ToString + Display,
     {
-        Self {
-            path: s.to_string(),
-        }
+        Self::new(s.to_string(), 0, Vec::new(), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blaze_explorer_lib::app::App;
+
+    #[test]
+    fn test_narrowed_query_reuses_the_walk() {
+        let mut app = App::new().unwrap();
+        let mut search = SearchFileshereSearch::new(app.get_app_context(), SearchConfig::default());
+        let handle = SearchHandle::detached();
+        let cached_files = |search: &SearchFileshereSearch| {
+            search
+                .candidates
+                .lock()
+                .as_ref()
+                .map(|candidates| candidates.files.clone())
+                .unwrap()
+        };
+
+        search.search("fold".to_string(), &handle);
+        let walked = cached_files(&search);
+        let n_matched = search.n_matched;
+        search.search("folder_2".to_string(), &handle);
+        assert!(Arc::ptr_eq(&walked, &cached_files(&search)));
+        assert!(search.n_matched < n_matched);
+        assert!(search.results[0].path.ends_with("tests/folder_2"));
+
+        //Refreshing walks again, into a new cache
+        let previous_cache = search.candidates.clone();
+        search.refresh();
+        assert!(previous_cache.is_discarded());
+        search.search("folder_2".to_string(), &handle);
+        assert!(!Arc::ptr_eq(&walked, &cached_files(&search)));
     }
 }
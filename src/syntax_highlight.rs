@@ -0,0 +1,143 @@
+//Syntax highlighting for file previews. The grammars and themes bundled with syntect are used, so
+//nothing is loaded from disk or the network at runtime.
+
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Style as SyntectStyle, Theme, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+/// Theme used unless another one is configured, meant for dark terminals
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+}
+
+/// Names of the bundled themes which can be configured
+pub fn theme_names() -> Vec<&'static str> {
+    THEME_SET.themes.keys().map(|name| name.as_str()).collect()
+}
+
+fn find_theme(name: &str) -> &'static Theme {
+    THEME_SET
+        .themes
+        .get(name)
+        .or_else(|| THEME_SET.themes.get(DEFAULT_THEME))
+        .expect("the default theme is bundled with syntect")
+}
+
+/// Highlight the first `max_lines` lines of the file contents with the named theme. The language
+/// is detected from the file extension or name, falling back to the shebang. Only foreground
+/// colours and font styles are applied, so the preview keeps the background of the app theme.
+/// Files in an unknown language are returned unstyled.
+pub fn highlight_contents(
+    path: &Path,
+    contents: &str,
+    max_lines: usize,
+    theme: &str,
+) -> Vec<Line<'static>> {
+    let first_line = contents.lines().next().unwrap_or_default();
+    let syntax = match find_syntax(path, first_line) {
+        Some(syntax) => syntax,
+        None => {
+            return contents
+                .lines()
+                .take(max_lines)
+                .map(|line| Line::from(line.to_string()))
+                .collect()
+        }
+    };
+    let mut highlighter = HighlightLines::new(syntax, find_theme(theme));
+    LinesWithEndings::from(contents)
+        .take(max_lines)
+        .map(|line| match highlighter.highlight_line(line, &SYNTAX_SET) {
+            Ok(ranges) => Line::from(
+                ranges
+                    .into_iter()
+                    .map(|(style, text)| {
+                        Span::styled(
+                            text.trim_end_matches(['\n', '\r']).to_string(),
+                            convert_style(style),
+                        )
+                    })
+                    .collect::<Vec<Span>>(),
+            ),
+            Err(_) => Line::from(line.trim_end_matches(['\n', '\r']).to_string()),
+        })
+        .collect()
+}
+
+/// Grammar for the file, or None if it is plain text
+fn find_syntax(path: &Path, first_line: &str) -> Option<&'static SyntaxReference> {
+    //syntect also registers whole file names such as "Makefile" as extensions
+    let by_name = |name: Option<&std::ffi::OsStr>| {
+        name.and_then(|n| n.to_str())
+            .and_then(|n| SYNTAX_SET.find_syntax_by_extension(n))
+    };
+    by_name(path.extension())
+        .or_else(|| by_name(path.file_name()))
+        .or_else(|| SYNTAX_SET.find_syntax_by_first_line(first_line))
+        .filter(|syntax| syntax.name != SYNTAX_SET.find_syntax_plain_text().name)
+}
+
+fn convert_style(style: SyntectStyle) -> Style {
+    let foreground = style.foreground;
+    let mut converted = Style::default().fg(Color::Rgb(foreground.r, foreground.g, foreground.b));
+    for (font_style, modifier) in [
+        (FontStyle::BOLD, Modifier::BOLD),
+        (FontStyle::ITALIC, Modifier::ITALIC),
+        (FontStyle::UNDERLINE, Modifier::UNDERLINED),
+    ] {
+        if style.font_style.contains(font_style) {
+            converted = converted.add_modifier(modifier);
+        }
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_contents() {
+        let contents = "fn main() {}\nlet x = 1;\nlet y = 2;\n";
+        let lines = highlight_contents(Path::new("main.rs"), contents, 2, DEFAULT_THEME);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].spans.len() > 1);
+        assert!(lines[0].spans.iter().all(|span| span.style.fg.is_some()));
+
+        let script = highlight_contents(
+            Path::new("run"),
+            "#!/bin/bash\necho hi\n",
+            10,
+            DEFAULT_THEME,
+        );
+        assert!(script[1].spans.iter().any(|span| span.style.fg.is_some()));
+
+        let plain = highlight_contents(Path::new("notes.txt"), "fn main() {}\n", 10, DEFAULT_THEME);
+        assert_eq!(plain, vec![Line::from("fn main() {}")]);
+    }
+
+    #[test]
+    fn test_configured_theme() {
+        assert!(theme_names().contains(&"InspiredGitHub"));
+        let contents = "fn main() {}\n";
+        let dark = highlight_contents(Path::new("main.rs"), contents, 1, DEFAULT_THEME);
+        let light = highlight_contents(Path::new("main.rs"), contents, 1, "InspiredGitHub");
+        assert_ne!(dark, light);
+        //unknown themes fall back to the default one
+        let unknown = highlight_contents(Path::new("main.rs"), contents, 1, "no such theme");
+        assert_eq!(unknown, dark);
+    }
+}
@@ -1,23 +1,83 @@
 use blaze_explorer_lib::{
-    action::Action, app_context::AppContext, query::Query, themes::CustomTheme, tools::center_rect,
+    action::{Action, AppAction},
+    app_context::AppContext,
+    line_entry::LineEntry,
+    mode::Mode,
+    query::Query,
+    themes::CustomTheme,
 };
 use color_eyre::eyre::Result;
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    crossterm::event::KeyEvent,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState},
     Frame,
 };
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::path::PathBuf;
 
-use crate::sfs_telescope::SearchFileshereSearch;
+use crate::{
+    batch_actions::{copy_paths, delete_paths, move_paths, BatchAction, PendingBatch},
+    config::{Picker, TelescopeConfig},
+    extended_query::{ExtendedQuery, MatchMode, QueryError},
+    git_log_telescope::GitLogSearch,
+    git_status_telescope::GitStatusSearch,
+    keymap_telescope::KeymapSearch,
+    layout::LayoutConfig,
+    live_grep_telescope::LiveGrepSearch,
+    search_worker::{SearchHandle, SearchWorker},
+    sfs_telescope::SearchFileshereSearch,
+};
 
 #[derive(Debug, Clone)]
 pub struct TelescopeBackend {
     pub query: Query,
     pub search: Box<dyn TelescopeSearch>,
     pub table_state: TableState,
+    pub layout: LayoutConfig,
     theme: CustomTheme,
+    //Colour of the matched characters from the configuration
+    match_highlight: Option<Color>,
+    worker: SearchWorker,
+    current_directory: PathBuf,
+    //Paths of the results marked for a batch action
+    marked: Vec<PathBuf>,
+    //Batch action waiting for the user to confirm it
+    pending_batch: Option<PendingBatch>,
+    //Whether the results are searched again before they are drawn, after a batch action
+    refresh_on_draw: bool,
+    //Directory typed in by the user when moving results
+    destination: Query,
+    message: Option<String>,
+    //Why the query typed in can't be parsed
+    query_error: Option<String>,
+    //How far the preview of each result is scrolled, by the displayed text of the result
+    preview_scroll: HashMap<String, u16>,
+    //Height of the preview contents during the last draw, used to scroll by half a page
+    preview_height: u16,
+    //Displayed text of the result to select again once the results have been sorted
+    reselect: Option<String>,
+}
+
+/// Kinds of files which the file searches can include or leave out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchFilter {
+    Hidden,
+    Ignored,
+}
+
+/// Movements of the preview pane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewScroll {
+    Down,
+    Up,
+    HalfPageDown,
+    HalfPageUp,
+    Top,
 }
 
 impl PartialEq for TelescopeBackend {
@@ -25,11 +85,19 @@ impl PartialEq for TelescopeBackend {
         self.query == other.query
             && self.search.clone() == other.search.clone()
             && self.table_state == other.table_state
+            && self.marked == other.marked
+            && self.pending_batch == other.pending_batch
     }
 }
 
 impl TelescopeBackend {
     pub fn confirm_result(&mut self) -> Option<Action> {
+        if let Some(batch) = self.pending_batch.take() {
+            return Some(self.run_batch(batch));
+        }
+        if let Some(location) = self.marked_location() {
+            return Some(Action::AppAct(AppAction::ShowInFolder(location)));
+        }
         if let Some(id) = self.table_state.selected() {
             return self.search.confirm_result(id);
         }
@@ -38,6 +106,9 @@ impl TelescopeBackend {
 
     pub fn next_result(&mut self) {
         let n_results = self.search.n_results();
+        if n_results == 0 {
+            return;
+        }
         let i = match self.table_state.selected() {
             Some(i) => {
                 if i == n_results - 1 {
@@ -52,6 +123,9 @@ impl TelescopeBackend {
     }
     pub fn previous_result(&mut self) {
         let n_results = self.search.n_results();
+        if n_results == 0 {
+            return;
+        }
         let i = match self.table_state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -65,93 +139,562 @@ impl TelescopeBackend {
         self.table_state.select(Some(i));
     }
 
+    fn pending_action(&self) -> Option<&BatchAction> {
+        self.pending_batch.as_ref().map(|batch| &batch.action)
+    }
+
+    pub fn push_char(&mut self, ch: char) {
+        match self.pending_action() {
+            Some(BatchAction::Move) => self.destination.append_char(ch),
+            _ => {
+                self.query.append_char(ch);
+                self.on_query_edited();
+            }
+        }
+    }
+
+    pub fn drop_char(&mut self) {
+        match self.pending_action() {
+            Some(BatchAction::Move) => self.destination.drop_char(),
+            _ => {
+                self.query.drop_char();
+                self.on_query_edited();
+            }
+        }
+    }
+
+    pub fn erase_text(&mut self) {
+        match self.pending_action() {
+            Some(BatchAction::Move) => self.destination.clear_contents(),
+            _ => {
+                self.query.clear_contents();
+                self.on_query_edited();
+            }
+        }
+    }
+
+    /// Editing the query cancels any batch action waiting for confirmation
+    fn on_query_edited(&mut self) {
+        self.pending_batch = None;
+        self.message = None;
+        self.reselect = None;
+        self.update_search_query(self.query.get_contents());
+    }
+
+    /// Mark or unmark the selected result and move on to the next one
+    pub fn toggle_mark(&mut self) {
+        let selected_path = self
+            .table_state
+            .selected()
+            .and_then(|id| self.search.get_path(id));
+        if let Some(path) = selected_path {
+            match self.marked.iter().position(|p| *p == path) {
+                Some(i) => {
+                    self.marked.remove(i);
+                }
+                None => self.marked.push(path),
+            }
+        }
+        self.next_result();
+    }
+
+    /// Paths a batch action applies to - the marked results, or the selected one if none are
+    /// marked
+    /// The marked path if only one is marked, otherwise the closest directory containing all of
+    /// them
+    fn marked_location(&self) -> Option<PathBuf> {
+        let (first, rest) = self.marked.split_first()?;
+        if rest.is_empty() {
+            return Some(first.clone());
+        }
+        let mut directory = first.parent()?;
+        while !rest.iter().all(|path| path.starts_with(directory)) {
+            directory = directory.parent()?;
+        }
+        Some(directory.to_path_buf())
+    }
+
+    fn batch_paths(&self) -> Vec<PathBuf> {
+        match self.marked.is_empty() {
+            false => self.marked.clone(),
+            true => self
+                .table_state
+                .selected()
+                .and_then(|id| self.search.get_path(id))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Copying happens straight away, deleting and moving wait for the user to confirm. The paths
+    /// are taken when the action starts, so that moving the selection in the meantime doesn't
+    /// change what the action applies to.
+    pub fn start_batch(&mut self, batch: BatchAction) {
+        let paths = self.batch_paths();
+        if paths.is_empty() {
+            self.message = Some("No files selected".to_string());
+            return;
+        }
+        if batch == BatchAction::Move {
+            self.destination = Query::default();
+            for ch in format!("{}/", self.current_directory.display()).chars() {
+                self.destination.append_char(ch);
+            }
+        }
+        match batch {
+            BatchAction::CopyPaths => self.message = Some(copy_paths(&paths)),
+            action => self.pending_batch = Some(PendingBatch { action, paths }),
+        }
+    }
+
+    fn run_batch(&mut self, batch: PendingBatch) -> Action {
+        let paths = batch.paths;
+        let action = match batch.action {
+            BatchAction::CopyPaths => Action::AppAct(AppAction::DisplayMessage(copy_paths(&paths))),
+            BatchAction::Delete => delete_paths(&paths),
+            BatchAction::Move => {
+                let destination = self.current_directory.join(self.destination.get_contents());
+                move_paths(&paths, &destination)
+            }
+        };
+        self.marked.clear();
+        //The explorer deletes and moves the files once it gets the action, so the results are
+        //searched again on the next draw
+        self.refresh_on_draw = true;
+        action
+    }
+
+    /// Scroll the preview of the selected result, remembering the position for what it shows.
+    /// Scrolling stops at the end of the preview once it is drawn.
+    pub fn scroll_preview(&mut self, scroll: PreviewScroll) {
+        let preview_id = match self.selected_preview_id() {
+            Some(preview_id) => preview_id,
+            None => return,
+        };
+        let half_page = (self.preview_height / 2).max(1);
+        let offset = self.preview_scroll.entry(preview_id).or_insert(0);
+        *offset = match scroll {
+            PreviewScroll::Down => offset.saturating_add(1),
+            PreviewScroll::Up => offset.saturating_sub(1),
+            PreviewScroll::HalfPageDown => offset.saturating_add(half_page),
+            PreviewScroll::HalfPageUp => offset.saturating_sub(half_page),
+            PreviewScroll::Top => 0,
+        };
+    }
+
+    /// Title of the results block, counting the selected, shown and matched results
+    pub fn results_title(&self) -> String {
+        let selected = self.table_state.selected().map_or(0, |id| id + 1);
+        let title = format!(
+            "Results {} / {} / {}",
+            selected,
+            self.search.n_results(),
+            self.search.n_matched()
+        );
+        match self.search.is_truncated() {
+            true => format!("{} - {}", title, self.search.truncation_note()),
+            false => title,
+        }
+    }
+
+    /// What the preview of the selected result shows, falling back to the text of the result
+    fn selected_preview_id(&self) -> Option<String> {
+        let id = self.table_state.selected()?;
+        self.search
+            .preview_id(id)
+            .or_else(|| self.selected_result_text())
+    }
+
+    fn selected_result_text(&self) -> Option<String> {
+        let id = self.table_state.selected()?;
+        self.search
+            .get_result_rows()
+            .into_iter()
+            .nth(id)
+            .map(|r| r.text.text)
+    }
+
+    /// Start the search for the query in the background, replacing any search still running
     pub fn update_search_query(&mut self, query: String) {
-        self.search.search(query);
+        //Keep the results of the last valid query until the query can be parsed again. A search
+        //still running would replace the search with an outdated copy, so it is dropped.
+        if let Err(e) = self.search.check_query(&query) {
+            self.query_error = Some(e.to_string());
+            self.worker.cancel();
+            return;
+        }
+        self.query_error = None;
+        self.worker.start(&*self.search, query);
+    }
+
+    /// Discard the cached data of the search and run the current query again
+    pub fn refresh(&mut self) {
+        self.search.refresh();
+        self.update_search_query(self.query.get_contents());
+    }
+
+    pub fn toggle_filter(&mut self, filter: SearchFilter) {
+        self.search.toggle_filter(filter);
+        self.refresh();
+    }
+
+    /// Switch to the next sort mode and search again to sort the results, keeping the selected
+    /// result selected
+    pub fn cycle_sort(&mut self) {
+        self.reselect = self.selected_result_text();
+        self.search.cycle_sort();
+        self.update_search_query(self.query.get_contents());
+    }
+
+    /// Switch the query mode and search for the query again, as it may not be valid anymore
+    pub fn cycle_query_mode(&mut self) {
+        self.search.cycle_query_mode();
+        self.update_search_query(self.query.get_contents());
+    }
+
+    /// Take in the results streamed by the background search so far
+    pub fn poll_search(&mut self) {
+        if let Some(search) = self.worker.poll() {
+            self.search = search;
+            self.on_search_updated();
+        }
+    }
+
+    /// Block until the background search finishes and take in its results
+    pub fn wait_for_search(&mut self) {
+        if let Some(search) = self.worker.wait() {
+            self.search = search;
+            self.on_search_updated();
+        }
+    }
+
+    /// Once the search finishes, select the result which was selected before sorting, if it is
+    /// still there
+    fn on_search_updated(&mut self) {
+        if self.worker.is_searching() {
+            return;
+        }
+        if let Some(text) = self.reselect.take() {
+            let rows = self.search.get_result_rows();
+            if let Some(id) = rows.iter().position(|r| r.text.text == text) {
+                self.table_state.select(Some(id));
+            }
+        }
     }
 }
 impl TelescopeBackend {
     pub fn draw(&mut self, frame: &mut Frame, area: Rect) -> Result<()> {
-        //override the area to only take 80x80 of the total are
-        let area = center_rect(area, Constraint::Percentage(80), Constraint::Percentage(80));
-        frame.render_widget(Clear, area);
-        //split the area vertically 60/40
-        let chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
-            .split(area);
-        //split the left chunk into results and query, leaving one line for query
-        let list_query_split = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Fill(1), Constraint::Length(3)])
-            .split(chunks[0]);
-        let result_area = list_query_split[0];
-        let query_area = list_query_split[1];
-        let preview_area = chunks[1];
-        let results_block = Block::default().borders(Borders::ALL).title("Results");
-        let query_block = Block::default()
+        if std::mem::take(&mut self.refresh_on_draw) {
+            self.refresh();
+        }
+        self.poll_search();
+        //split the area into the results, the query prompt and the preview, as configured
+        let areas = self.layout.split(area);
+        frame.render_widget(Clear, areas.popup);
+        let result_area = areas.results;
+        let query_area = areas.prompt;
+        let results_block = Block::default()
             .borders(Borders::ALL)
-            .title(self.search.display());
+            .title(self.results_title());
+        let results_block = match self.marked.len() {
+            0 => results_block,
+            n => results_block.title(format!("{} marked", n)),
+        };
+        let n_batch_paths = self
+            .pending_batch
+            .as_ref()
+            .map_or(0, |batch| batch.paths.len());
+        let query_title = match (self.pending_action(), &self.message) {
+            (Some(BatchAction::Delete), _) => {
+                format!("Delete {} item(s)? <CR> to confirm", n_batch_paths)
+            }
+            (Some(BatchAction::Move), _) => format!("Move {} item(s) to:", n_batch_paths),
+            (_, Some(message)) => message.clone(),
+            _ => match (&self.query_error, self.worker.is_searching()) {
+                (Some(error), _) => format!("Invalid query: {}", error),
+                (None, true) => format!("{} - searching…", self.search.display()),
+                (None, false) => self.search.display(),
+            },
+        };
+        let query_block = Block::default().borders(Borders::ALL).title(query_title);
 
         // this type is responsible for rendering the query block - this is just a paragraph with
-        // the query
-        let query_paragraph = Paragraph::new(self.query.contents.clone());
+        // the query (or the destination when moving the marked results)
+        let prompt_contents = match self.pending_action() {
+            Some(BatchAction::Move) => self.destination.contents.clone(),
+            _ => self.query.contents.clone(),
+        };
+        let query_paragraph = Paragraph::new(prompt_contents);
         let query_paragraph = query_paragraph.block(query_block);
 
         frame.render_widget(query_paragraph, query_area);
 
-        //create a table from the vector of results
-
-        let rows = (*self.search)
-            .get_results_list()
-            .clone()
+        //create a table from the rows of results, highlighting the characters matched by the
+        //query, with the columns of the search sized to their longest value
+        let match_style = self.match_style();
+        let results = (*self.search).get_result_rows();
+        let column_names = self.search.column_names();
+        let mut column_widths = Vec::new();
+        for column in 0..results.first().map_or(0, |r| r.columns.len()) {
+            let width = results
+                .iter()
+                .map(|r| r.columns[column].chars().count())
+                .chain(
+                    column_names
+                        .get(column + 1)
+                        .map(|name| name.chars().count()),
+                )
+                .max()
+                .unwrap_or(0);
+            column_widths.push(width);
+        }
+        //the text gets what is left inside the borders after the mark and the columns, each
+        //column being preceded by a space
+        let text_width = (result_area.width as usize)
+            .saturating_sub(2 + 2)
+            .saturating_sub(column_widths.iter().map(|width| width + 1).sum());
+        let widths = [Constraint::Length(1), Constraint::Fill(1)]
+            .into_iter()
+            .chain(
+                column_widths
+                    .iter()
+                    .map(|width| Constraint::Length(*width as u16)),
+            )
+            .collect::<Vec<Constraint>>();
+        let rows = results
             .into_iter()
-            .map(|r| Row::new([Cell::from(r)]))
+            .enumerate()
+            .map(|(id, r)| {
+                let mark = match self.search.get_path(id) {
+                    Some(path) if self.marked.contains(&path) => "+",
+                    _ => " ",
+                };
+                let text = self.search.fit_result(r.text, text_width);
+                let cells = [Cell::from(mark), Cell::from(text.to_line(match_style))]
+                    .into_iter()
+                    .chain(r.columns.into_iter().map(Cell::from));
+                Row::new(cells)
+            })
             .collect::<Vec<Row>>();
 
         match (self.table_state.selected(), rows.is_empty()) {
             (None, false) => self.table_state.select(Some(0)),
             (Some(_), true) => self.table_state.select(None),
+            //results streamed in by a newer search may be fewer than before
+            (Some(i), false) if i >= rows.len() => self.table_state.select(Some(rows.len() - 1)),
             _ => {}
         }
-        let widths = [Constraint::Percentage(100)];
         let table = Table::new(rows, widths)
             .block(results_block)
             .highlight_style(self.theme.selected_row_telescope);
+        let table = match column_names.is_empty() {
+            true => table,
+            false => {
+                let header = [String::new()].into_iter().chain(column_names);
+                table.header(Row::new(header).style(Style::default().add_modifier(Modifier::BOLD)))
+            }
+        };
         frame.render_stateful_widget(table, result_area, &mut self.table_state);
 
         //render the preview - this is handled by the result type (or at least for now)
-        self.search
-            .preview_result(self.table_state.selected(), frame, preview_area)?;
+        let preview_area = match areas.preview {
+            Some(preview_area) => preview_area,
+            None => {
+                self.preview_height = 0;
+                return Ok(());
+            }
+        };
+        self.preview_height = preview_area.height.saturating_sub(2);
+        let preview_id = self.selected_preview_id();
+        let scroll = preview_id
+            .as_ref()
+            .and_then(|preview_id| self.preview_scroll.get(preview_id))
+            .copied()
+            .unwrap_or(0);
+        let applied_scroll =
+            self.search
+                .preview_result(self.table_state.selected(), scroll, frame, preview_area)?;
+        //keep the position at the end of the preview, so that scrolling back up works right away
+        if let Some(preview_id) = preview_id {
+            if applied_scroll != scroll {
+                self.preview_scroll.insert(preview_id, applied_scroll);
+            }
+        }
 
         Ok(())
     }
 
-    pub fn new_sfs(search_context: AppContext) -> Self {
-        //FIXME: Create a separate contructor for each type of search
+    /// Style of the matched characters. They stay visible on the selected row, which only gets
+    /// its background from the theme. Without a configured colour they are yellow, or red when
+    /// the selected row is yellow.
+    fn match_style(&self) -> Style {
+        let colour = match (self.match_highlight, self.theme.selected_row_telescope.bg) {
+            (Some(colour), _) => colour,
+            (None, Some(Color::Yellow | Color::LightYellow)) => Color::Red,
+            (None, _) => Color::Yellow,
+        };
+        Style::default().fg(colour).add_modifier(Modifier::BOLD)
+    }
+
+    fn new(
+        search: Box<dyn TelescopeSearch>,
+        search_context: AppContext,
+        config: &TelescopeConfig,
+        picker: Picker,
+    ) -> Self {
         Self {
             query: Query::default(),
-            search: Box::new(SearchFileshereSearch::new(search_context)),
+            search,
             table_state: TableState::default(),
+            layout: config.layout_config(picker),
             theme: CustomTheme::default(),
+            match_highlight: config.theme.match_highlight,
+            worker: SearchWorker::default(),
+            current_directory: search_context.current_directory,
+            marked: Vec::new(),
+            pending_batch: None,
+            refresh_on_draw: false,
+            destination: Query::default(),
+            message: None,
+            query_error: None,
+            preview_scroll: HashMap::new(),
+            preview_height: 0,
+            reselect: None,
         }
     }
+
+    pub fn new_sfs(search_context: AppContext, config: &TelescopeConfig) -> Self {
+        let search =
+            SearchFileshereSearch::new(search_context.clone(), config.search_config(Picker::Files));
+        Self::new(Box::new(search), search_context, config, Picker::Files)
+    }
+
+    pub fn new_live_grep(search_context: AppContext, config: &TelescopeConfig) -> Self {
+        let search = LiveGrepSearch::new(
+            search_context.clone(),
+            config.search_config(Picker::LiveGrep),
+        );
+        Self::new(Box::new(search), search_context, config, Picker::LiveGrep)
+    }
+
+    /// The commits are read by the first search, which is started right away so that they are
+    /// listed before anything is typed
+    pub fn new_git_log(search_context: AppContext, config: &TelescopeConfig) -> Self {
+        let search =
+            GitLogSearch::new(search_context.clone(), config.search_config(Picker::GitLog));
+        let mut backend = Self::new(Box::new(search), search_context, config, Picker::GitLog);
+        backend.update_search_query(String::new());
+        backend
+    }
+
+    pub fn new_git_status(search_context: AppContext, config: &TelescopeConfig) -> Self {
+        let mut backend = Self::new(
+            Box::new(GitStatusSearch::new(search_context.clone())),
+            search_context,
+            config,
+            Picker::GitStatus,
+        );
+        backend.update_search_query(String::new());
+        backend
+    }
+
+    pub fn new_keymaps(
+        search_context: AppContext,
+        config: &TelescopeConfig,
+        bindings: HashMap<(Mode, Vec<KeyEvent>), String>,
+    ) -> Self {
+        Self::new(
+            Box::new(KeymapSearch::new(bindings)),
+            search_context,
+            config,
+            Picker::Keymaps,
+        )
+    }
 }
 pub trait TelescopeSearch: TelescopeSearchSuper {
-    /// Perform necessary actions to return the search results
-    fn search(&mut self, query: String);
+    /// Perform necessary actions to return the search results. This runs on a worker thread -
+    /// long searches should stop once the handle is cancelled and publish partial results
+    /// through it
+    fn search(&mut self, query: String, handle: &SearchHandle);
 
-    fn get_results_list(&self) -> Vec<String>;
+    /// Return the displayed results together with the characters matched by the query and the
+    /// values of the extra columns
+    fn get_result_rows(&self) -> Vec<ResultRow>;
+
+    /// Fit the displayed text of a result into the width of its column. The text is left as it is
+    /// by default, to be cut off at the edge of the table.
+    fn fit_result(&self, text: HighlightedText, _width: usize) -> HighlightedText {
+        text
+    }
+
+    /// Header of the results table - the name of the result text followed by the names of the
+    /// extra columns. No header is shown if empty.
+    fn column_names(&self) -> Vec<String> {
+        Vec::new()
+    }
 
     /// Determine what happens when the user confirms a result
     fn confirm_result(&mut self, id: usize) -> Option<Action>;
 
-    fn preview_result(&self, id: Option<usize>, frame: &mut Frame, area: Rect) -> Result<()>;
+    /// Render the preview of the result, scrolled down by the given number of lines at most.
+    /// Returns the scroll applied, which stops once the end of the preview is in the area.
+    fn preview_result(
+        &self,
+        id: Option<usize>,
+        scroll: u16,
+        frame: &mut Frame,
+        area: Rect,
+    ) -> Result<u16>;
 
     fn display(&self) -> String;
 
     fn n_results(&self) -> usize;
+
+    /// Number of results matched by the query, including the ones dropped by the result limit
+    fn n_matched(&self) -> usize {
+        self.n_results()
+    }
+
+    /// Whether the result limit left out some of the matches
+    fn is_truncated(&self) -> bool {
+        self.n_matched() > self.n_results()
+    }
+
+    /// Shown in the results title when the search is truncated
+    fn truncation_note(&self) -> String {
+        "truncated, refine the query".to_string()
+    }
+
+    /// Check that the query can be parsed before searching for it
+    fn check_query(&self, query: &str) -> Result<(), QueryError> {
+        ExtendedQuery::parse(query).map(|_| ())
+    }
+
+    /// Drop any data cached between queries, so that the next search starts from scratch
+    fn refresh(&mut self) {}
+
+    /// Include or exclude a kind of files from the search. Searches which don't walk the
+    /// filesystem ignore this.
+    fn toggle_filter(&mut self, _filter: SearchFilter) {}
+
+    /// Switch to the next query mode, for the searches which support them
+    fn cycle_query_mode(&mut self) {}
+
+    /// Order the results by the next column, for the searches which can be sorted
+    fn cycle_sort(&mut self) {}
+
+    /// Path of the file or directory behind the result, if there is one
+    fn get_path(&self, _id: usize) -> Option<PathBuf> {
+        None
+    }
+
+    /// Identifies what the preview of the result shows, so that its scroll position follows the
+    /// result when the results change. The path of the result by default.
+    fn preview_id(&self, id: usize) -> Option<String> {
+        self.get_path(id).map(|path| path.display().to_string())
+    }
 }
-pub trait TelescopeSearchSuper: Debug {
+pub trait TelescopeSearchSuper: Debug + Send {
     fn clone_box(&self) -> Box<dyn TelescopeSearch>;
 }
 impl<T> TelescopeSearchSuper for T
@@ -175,11 +718,363 @@ impl PartialEq for Box<dyn TelescopeSearch> {
     }
 }
 
+/// Row of the results table - the highlighted result followed by the values of the extra columns
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ResultRow {
+    pub text: HighlightedText,
+    pub columns: Vec<String>,
+}
+
+impl From<HighlightedText> for ResultRow {
+    fn from(text: HighlightedText) -> Self {
+        Self {
+            text,
+            columns: Vec::new(),
+        }
+    }
+}
+
+/// Text displayed in the results list along with the character positions matched by the query
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HighlightedText {
+    pub text: String,
+    pub positions: Vec<usize>,
+}
+
+impl HighlightedText {
+    pub fn new(text: String, positions: Vec<usize>) -> Self {
+        Self { text, positions }
+    }
+
+    /// Highlight the characters matched by the query within the text, ignoring case
+    pub fn from_query(text: String, query: &str) -> Self {
+        let positions = ExtendedQuery::parse(query)
+            .ok()
+            .and_then(|query| query.matches(&text, MatchMode::Substring, true))
+            .map(|found| found.positions)
+            .unwrap_or_default();
+        Self::new(text, positions)
+    }
+
+    /// Split the text into spans, applying the style to the matched characters
+    pub fn to_line(&self, match_style: Style) -> Line<'static> {
+        let mut spans = Vec::new();
+        let mut current = String::new();
+        let mut current_matched = false;
+        for (id, ch) in self.text.chars().enumerate() {
+            let matched = self.positions.contains(&id);
+            if matched != current_matched && !current.is_empty() {
+                spans.push(styled_span(
+                    std::mem::take(&mut current),
+                    current_matched,
+                    match_style,
+                ));
+            }
+            current_matched = matched;
+            current.push(ch);
+        }
+        if !current.is_empty() {
+            spans.push(styled_span(current, current_matched, match_style));
+        }
+        Line::from(spans)
+    }
+}
+
+/// Limit the scroll of a preview with the given number of lines, so that it stops once its last
+/// line is at the bottom of the area inside the borders
+pub fn clamp_scroll(scroll: u16, n_lines: usize, area: Rect) -> u16 {
+    let visible = area.height.saturating_sub(2) as usize;
+    let max_scroll = n_lines.saturating_sub(visible).min(u16::MAX as usize) as u16;
+    scroll.min(max_scroll)
+}
+
+fn styled_span(text: String, matched: bool, match_style: Style) -> Span<'static> {
+    match matched {
+        true => Span::styled(text, match_style),
+        false => Span::raw(text),
+    }
+}
+
 pub trait TelescopeResult {
     // What is displayed in the result list on the left
     fn display(&self) -> String;
-    // What is rendered in the preview area when the user selects a result
-    fn preview(&self, frame: &mut Frame, area: Rect, preview_block: Block) -> Result<()>;
+    // What is rendered in the preview area when the user selects a result, returning the scroll
+    // applied
+    fn preview(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        preview_block: Block,
+        scroll: u16,
+    ) -> Result<u16>;
 
     fn from<S: ToString + Display>(s: S) -> Self;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file_columns::ResultColumn, file_preview::format_size};
+    use blaze_explorer_lib::app::App;
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::fs;
+
+    #[test]
+    fn test_highlighted_text_to_line() {
+        let match_style = Style::default().fg(Color::Yellow);
+        let highlighted = HighlightedText::new("src/lib.rs".to_string(), vec![4, 5, 6]);
+        let expected_line = Line::from(vec![
+            Span::raw("src/"),
+            Span::styled("lib", match_style),
+            Span::raw(".rs"),
+        ]);
+        assert_eq!(highlighted.to_line(match_style), expected_line);
+    }
+
+    #[test]
+    fn test_match_style_contrasts_with_selected_row() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        for bg in [Color::Blue, Color::Yellow, Color::Reset] {
+            sfs.theme.selected_row_telescope = Style::default().bg(bg);
+            assert_ne!(sfs.match_style().fg, Some(bg));
+        }
+
+        let mut config = TelescopeConfig::default();
+        config.theme.match_highlight = Some(Color::Magenta);
+        let sfs = TelescopeBackend::new_sfs(app.get_app_context(), &config);
+        assert_eq!(sfs.match_style().fg, Some(Color::Magenta));
+    }
+
+    #[test]
+    fn test_toggle_mark() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("folder".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        let selected_path = sfs.search.get_path(0).unwrap();
+        assert_eq!(sfs.batch_paths(), vec![selected_path.clone()]);
+
+        sfs.toggle_mark();
+        assert_eq!(sfs.marked, vec![selected_path]);
+        assert_eq!(sfs.table_state.selected(), Some(1));
+
+        sfs.table_state.select(Some(0));
+        sfs.toggle_mark();
+        assert!(sfs.marked.is_empty());
+    }
+
+    #[test]
+    fn test_confirm_shows_the_marked_results() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let tests = ctx.current_directory.join("tests");
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("folder".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        let text = tests.join("folder_1/example_text.txt");
+        sfs.marked = vec![text.clone()];
+        assert_eq!(
+            sfs.confirm_result(),
+            Some(Action::AppAct(AppAction::ShowInFolder(text)))
+        );
+
+        sfs.marked.push(tests.join("folder_2/notes.txt"));
+        assert_eq!(
+            sfs.confirm_result(),
+            Some(Action::AppAct(AppAction::ShowInFolder(tests)))
+        );
+
+        sfs.start_batch(BatchAction::Delete);
+        assert!(matches!(
+            sfs.confirm_result(),
+            Some(Action::AppAct(AppAction::DeletePaths(_)))
+        ));
+        assert!(sfs.marked.is_empty());
+        assert!(sfs.refresh_on_draw);
+    }
+
+    #[test]
+    fn test_pending_batch_keeps_its_paths() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("folder".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        let selected_path = sfs.search.get_path(0).unwrap();
+
+        sfs.start_batch(BatchAction::Delete);
+        sfs.next_result();
+        assert_eq!(
+            sfs.pending_batch,
+            Some(PendingBatch {
+                action: BatchAction::Delete,
+                paths: vec![selected_path],
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_query_keeps_previous_results() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("^tests folder_ | example_".to_string());
+        sfs.wait_for_search();
+        let n_results = sfs.search.n_results();
+        assert!(n_results > 0);
+
+        sfs.update_search_query("^tests folder_ |".to_string());
+        sfs.wait_for_search();
+        assert!(sfs.query_error.is_some());
+        assert_eq!(sfs.search.n_results(), n_results);
+
+        sfs.update_search_query("^tests folder_ | !xlsx".to_string());
+        sfs.wait_for_search();
+        assert_eq!(sfs.query_error, None);
+    }
+
+    #[test]
+    fn test_invalid_regex_is_reported() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.query.contents = "(folder".to_string();
+        sfs.update_search_query(sfs.query.get_contents());
+        sfs.wait_for_search();
+        assert_eq!(sfs.query_error, None);
+
+        //Ignore case, smart case, exact case and then regex
+        for _ in 0..3 {
+            sfs.cycle_query_mode();
+        }
+        sfs.wait_for_search();
+        assert!(sfs.search.display().contains("[regex"));
+        assert_eq!(
+            sfs.query_error,
+            Some("invalid regex: unclosed group".to_string())
+        );
+    }
+
+    #[test]
+    fn test_result_limit_and_depth() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut config = TelescopeConfig::default();
+        config.search.max_results = 1;
+        let mut sfs = TelescopeBackend::new_sfs(ctx.clone(), &config);
+        sfs.update_search_query("folder".to_string());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        assert_eq!(sfs.search.n_results(), 1);
+        assert!(sfs.search.n_matched() > 1);
+        assert_eq!(
+            sfs.results_title(),
+            format!(
+                "Results 1 / 1 / {} - truncated, refine the query",
+                sfs.search.n_matched()
+            )
+        );
+
+        config.search.max_depth = Some(1);
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &config);
+        sfs.update_search_query("example_file".to_string());
+        sfs.wait_for_search();
+        assert_eq!(sfs.results_title(), "Results 0 / 0 / 0");
+    }
+
+    #[test]
+    fn test_columns_and_sort() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut config = TelescopeConfig::default();
+        config.search.columns = vec![ResultColumn::Type, ResultColumn::Size];
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &config);
+        sfs.query.contents = "'folder_1/example_".to_string();
+        sfs.update_search_query(sfs.query.get_contents());
+        sfs.wait_for_search();
+        assert_eq!(sfs.search.column_names(), vec!["Path", "Type", "Size"]);
+
+        //the shorter path comes first among equal scores
+        sfs.table_state.select(Some(0));
+        assert!(sfs
+            .selected_result_text()
+            .unwrap()
+            .ends_with("example_text.txt"));
+
+        //cycle from the score to the basename, natural order, modification time and then size
+        for _ in 0..4 {
+            sfs.cycle_sort();
+        }
+        sfs.wait_for_search();
+        let rows = sfs.search.get_result_rows();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].text.text.ends_with("example_file.xlsx"));
+        //the sizes come from the fixtures, as checkouts can convert their line endings
+        let size = |path: &str| format_size(fs::metadata(path).unwrap().len(), true);
+        assert_eq!(
+            rows[0].columns,
+            vec!["file".to_string(), size("tests/folder_1/example_file.xlsx")]
+        );
+        assert_eq!(
+            rows[1].columns,
+            vec!["file".to_string(), size("tests/folder_1/example_text.txt")]
+        );
+        assert_eq!(sfs.table_state.selected(), Some(1));
+        assert!(sfs.search.display().contains("sorted by size"));
+    }
+
+    #[test]
+    fn test_preview_scroll_is_kept_per_result() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.update_search_query("folder".to_string());
+        sfs.wait_for_search();
+        sfs.preview_height = 10;
+        sfs.table_state.select(Some(0));
+        sfs.scroll_preview(PreviewScroll::HalfPageDown);
+        sfs.scroll_preview(PreviewScroll::Down);
+        sfs.table_state.select(Some(1));
+        sfs.scroll_preview(PreviewScroll::Up);
+
+        let paths = (0..2)
+            .map(|id| sfs.search.get_path(id).unwrap().display().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(sfs.preview_scroll.get(&paths[0]), Some(&6));
+        assert_eq!(sfs.preview_scroll.get(&paths[1]), Some(&0));
+
+        sfs.table_state.select(Some(0));
+        sfs.scroll_preview(PreviewScroll::Top);
+        assert_eq!(sfs.preview_scroll.get(&paths[0]), Some(&0));
+    }
+
+    #[test]
+    fn test_preview_scroll_stops_at_the_end() {
+        let mut app = App::new().unwrap();
+        let ctx = app.get_app_context();
+        let mut sfs = TelescopeBackend::new_sfs(ctx, &TelescopeConfig::default());
+        sfs.query.contents = "'example_text.txt".to_string();
+        sfs.update_search_query(sfs.query.get_contents());
+        sfs.wait_for_search();
+        sfs.table_state.select(Some(0));
+        for _ in 0..5 {
+            sfs.scroll_preview(PreviewScroll::Down);
+        }
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal
+            .draw(|frame| sfs.draw(frame, frame.size()).unwrap())
+            .unwrap();
+        //the two lines of the file fit in the preview, so it can't be scrolled
+        let path = sfs.search.get_path(0).unwrap().display().to_string();
+        assert_eq!(sfs.preview_scroll.get(&path), Some(&0));
+
+        assert_eq!(clamp_scroll(30, 100, Rect::new(0, 0, 10, 22)), 30);
+        assert_eq!(clamp_scroll(90, 100, Rect::new(0, 0, 10, 22)), 80);
+    }
+}
@@ -1,10 +1,52 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
 use blaze_explorer_lib::command::command_helpers::match_popup_call;
 use blaze_explorer_lib::plugin::plugin_helpers::{access_plugin, PluginFetchResult};
-use blaze_explorer_lib::{action::Action, app::App, command::Command};
+use blaze_explorer_lib::{
+    action::{Action, AppAction},
+    app::App,
+    app_context::AppContext,
+    command::Command,
+    mode::Mode,
+};
+use lazy_static::lazy_static;
+use ratatui::crossterm::event::KeyEvent;
+
+use crate::{
+    batch_actions::BatchAction,
+    config::{load_config, TelescopeConfig},
+    file_preview,
+    telescope_backend::{PreviewScroll, SearchFilter},
+    TelescopeWindow,
+};
+
+/// Details of the Telescope window, telling it apart from the popups of other plugins
+pub const WINDOW_DETAILS: &str = "Telescope";
+
+type WindowConstructor =
+    fn(AppContext, &TelescopeConfig, HashMap<(Mode, Vec<KeyEvent>), Action>) -> TelescopeWindow;
 
-use crate::TelescopeWindow;
 //Plugin functions
 pub fn open_sfs(app: &mut App) -> Option<Action> {
+    open_telescope_window(app, TelescopeWindow::new_sfs)
+}
+
+pub fn open_live_grep(app: &mut App) -> Option<Action> {
+    open_telescope_window(app, TelescopeWindow::new_live_grep)
+}
+
+pub fn open_git_log(app: &mut App) -> Option<Action> {
+    open_telescope_window(app, TelescopeWindow::new_git_log)
+}
+
+pub fn open_git_status(app: &mut App) -> Option<Action> {
+    open_telescope_window(app, TelescopeWindow::new_git_status)
+}
+
+/// Search the bindings of the plugin. The bindings of the explorer itself are not listed, as the
+/// explorer doesn't expose its keymap to plugins.
+pub fn open_keymaps(app: &mut App) -> Option<Action> {
     let ctx = app.get_app_context();
     let result = access_plugin(app, "Telescope");
     let plugin = match result {
@@ -12,13 +54,140 @@ pub fn open_sfs(app: &mut App) -> Option<Action> {
         PluginFetchResult::Ok(plugin) => plugin,
     };
     let popup_keymap = plugin.get_popup_keymap();
-    let popup = Box::new(TelescopeWindow::new_sfs(ctx, popup_keymap));
-    app.attach_popup(popup);
+    let mut bindings = plugin.get_plugin_bindings();
+    bindings.extend(plugin.get_popup_bindings());
+    let (config, message) = load_config();
+    file_preview::configure(&config.preview);
+    let window = TelescopeWindow::new_keymaps(ctx, &config, popup_keymap, bindings);
+    attach_window(app, window);
 
-    None
+    message.map(|message| Action::AppAct(AppAction::DisplayMessage(message)))
+}
+
+/// Fetch the popup keymap of the plugin and attach a window built by the given constructor
+fn open_telescope_window(app: &mut App, constructor: WindowConstructor) -> Option<Action> {
+    let ctx = app.get_app_context();
+    let result = access_plugin(app, "Telescope");
+    let plugin = match result {
+        PluginFetchResult::Err(action) => return action,
+        PluginFetchResult::Ok(plugin) => plugin,
+    };
+    let popup_keymap = plugin.get_popup_keymap();
+    let (config, message) = load_config();
+    file_preview::configure(&config.preview);
+    let window = constructor(ctx, &config, popup_keymap);
+    attach_window(app, window);
+
+    message.map(|message| Action::AppAct(AppAction::DisplayMessage(message)))
+}
+
+/// Attach the window as the popup, dropping the commands left over from an earlier window
+fn attach_window(app: &mut App, window: TelescopeWindow) {
+    take_window_commands();
+    app.attach_popup(Box::new(window));
 }
 
 //Popup functions
+
+lazy_static! {
+    //The app only exposes the popup through the PluginPopUp trait, so functionalities specific to
+    //the Telescope window are queued here and applied by the window before it is drawn next
+    static ref WINDOW_COMMANDS: Mutex<Vec<WindowCommand>> = Mutex::new(Vec::new());
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum WindowCommand {
+    Refresh,
+    ToggleMark,
+    StartBatch(BatchAction),
+    ScrollPreview(PreviewScroll),
+    ToggleFilter(SearchFilter),
+    CycleQueryMode,
+    CycleSort,
+}
+
+/// Queue the command if the open popup is a Telescope window - the functionalities can be bound
+/// while the popup of another plugin is open
+fn queue_window_command(app: &mut App, command: WindowCommand) -> Option<Action> {
+    let telescope_open = app
+        .popup
+        .as_ref()
+        .is_some_and(|popup| popup.display_details() == WINDOW_DETAILS);
+    if telescope_open {
+        if let Ok(mut commands) = WINDOW_COMMANDS.lock() {
+            commands.push(command);
+        }
+    }
+    None
+}
+
+/// Remove and return the commands queued for the Telescope window
+pub fn take_window_commands() -> Vec<WindowCommand> {
+    match WINDOW_COMMANDS.lock() {
+        Ok(mut commands) => std::mem::take(&mut *commands),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn telescope_refresh(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::Refresh)
+}
+
+pub fn telescope_toggle_mark(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ToggleMark)
+}
+
+pub fn telescope_copy_paths(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::StartBatch(BatchAction::CopyPaths))
+}
+
+pub fn telescope_delete_marked(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::StartBatch(BatchAction::Delete))
+}
+
+pub fn telescope_move_marked(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::StartBatch(BatchAction::Move))
+}
+
+pub fn telescope_preview_scroll_down(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ScrollPreview(PreviewScroll::Down))
+}
+
+pub fn telescope_preview_scroll_up(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ScrollPreview(PreviewScroll::Up))
+}
+
+pub fn telescope_preview_half_page_down(app: &mut App) -> Option<Action> {
+    queue_window_command(
+        app,
+        WindowCommand::ScrollPreview(PreviewScroll::HalfPageDown),
+    )
+}
+
+pub fn telescope_preview_half_page_up(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ScrollPreview(PreviewScroll::HalfPageUp))
+}
+
+pub fn telescope_preview_top(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ScrollPreview(PreviewScroll::Top))
+}
+
+pub fn telescope_toggle_hidden(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ToggleFilter(SearchFilter::Hidden))
+}
+
+pub fn telescope_toggle_ignored(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::ToggleFilter(SearchFilter::Ignored))
+}
+
+pub fn telescope_cycle_query_mode(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::CycleQueryMode)
+}
+
+pub fn telescope_cycle_sort(app: &mut App) -> Option<Action> {
+    queue_window_command(app, WindowCommand::CycleSort)
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct TelescopeUpdateSearchQuery {
     query: String,